@@ -0,0 +1,301 @@
+// Headless bot-vs-bot simulation: plays complete matches between bots using the same game logic
+// as the server, without any sockets, and prints statistics for every rule variant.
+//
+//   cargo run --release --bin simulate -- --games 200 --seed 7 --players hard,normal,easy \
+//       --rules none --rules multi_card_play,draw_cards_skip_turn
+
+use std::{ collections::HashMap, env, process };
+
+use server::{
+    game_settings::{ GameSettings, Ruleset },
+    game_state::{ GameState, MAX_PLAYERS, MIN_PLAYERS },
+    playerpool::PlayerPool,
+    set_game_logging,
+    strategy::BotDifficulty,
+};
+
+// matches that run longer than this are given up on and reported as unfinished
+const MAX_TURNS_PER_MATCH: usize = 100_000;
+const RULE_NAMES: [&str; 3] = ["multi_card_play", "draw_until_playable", "draw_cards_skip_turn"];
+const USAGE: &str =
+    "usage: simulate [--games N] [--seed S] [--players easy,normal,hard] [--score-target T] [--hand-size H] [--rules none|RULE,RULE...]...";
+
+#[derive(Debug)]
+struct Config {
+    games: usize,
+    seed: u64,
+    players: Vec<BotDifficulty>,
+    score_target: u32,
+    hand_size: usize,
+    variants: Vec<Ruleset>,
+}
+
+#[derive(Debug)]
+struct MatchOutcome {
+    winner_seat: usize, // index into Config::players
+    winning_score: i32,
+    rounds: usize,
+    turns: usize,
+}
+
+fn parse_difficulty(name: &str) -> Result<BotDifficulty, String> {
+    match name {
+        "easy" => Ok(BotDifficulty::Easy),
+        "normal" => Ok(BotDifficulty::Normal),
+        "hard" => Ok(BotDifficulty::Hard),
+        _ => Err(format!("Unknown bot difficulty: {}", name)),
+    }
+}
+
+fn parse_rules(list: &str) -> Result<Ruleset, String> {
+    let mut rules = Ruleset {
+        multi_card_play: false,
+        draw_until_playable: false,
+        draw_cards_skip_turn: false,
+    };
+    for name in list.split(',').filter(|name| *name != "none") {
+        match name {
+            "multi_card_play" => {
+                rules.multi_card_play = true;
+            }
+            "draw_until_playable" => {
+                rules.draw_until_playable = true;
+            }
+            "draw_cards_skip_turn" => {
+                rules.draw_cards_skip_turn = true;
+            }
+            _ => {
+                return Err(format!("Unknown rule: {}", name));
+            }
+        }
+    }
+    Ok(rules)
+}
+
+impl Config {
+    fn settings(&self, rules: &Ruleset) -> GameSettings {
+        GameSettings {
+            max_players: self.players.len(),
+            hand_size: self.hand_size,
+            score_target: self.score_target,
+            rules: rules.clone(),
+            ..GameSettings::default()
+        }
+    }
+}
+
+fn describe_rules(rules: &Ruleset) -> String {
+    let enabled = [rules.multi_card_play, rules.draw_until_playable, rules.draw_cards_skip_turn];
+    let names: Vec<&str> = RULE_NAMES.iter()
+        .zip(enabled)
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    if names.is_empty() { "none".to_string() } else { names.join(", ") }
+}
+
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config {
+        games: 100,
+        seed: 1,
+        players: vec![BotDifficulty::Normal, BotDifficulty::Easy],
+        score_target: GameSettings::default().score_target,
+        hand_size: GameSettings::default().hand_size,
+        variants: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("Missing value for {}", flag))?;
+        match flag.as_str() {
+            "--games" => {
+                config.games = value.parse().map_err(|_| "Invalid number of games")?;
+            }
+            "--seed" => {
+                config.seed = value.parse().map_err(|_| "Invalid seed")?;
+            }
+            "--players" => {
+                config.players = value.split(',').map(parse_difficulty).collect::<Result<_, _>>()?;
+            }
+            "--score-target" => {
+                config.score_target = value.parse().map_err(|_| "Invalid score target")?;
+            }
+            "--hand-size" => {
+                config.hand_size = value.parse().map_err(|_| "Invalid hand size")?;
+            }
+            "--rules" => config.variants.push(parse_rules(value)?),
+            _ => {
+                return Err(format!("Unknown option: {}", flag));
+            }
+        }
+    }
+
+    if config.players.len() < MIN_PLAYERS || config.players.len() > MAX_PLAYERS {
+        return Err("Between 2 and 10 players are needed".to_string());
+    }
+    //the same limits a table on the server is held to
+    config.settings(&Ruleset::default()).validate()?;
+    //without --rules, compare no house rules against each house rule on its own
+    if config.variants.is_empty() {
+        config.variants.push(parse_rules("none")?);
+        for name in RULE_NAMES {
+            config.variants.push(parse_rules(name)?);
+        }
+    }
+    Ok(config)
+}
+
+//play one match to the score target. Seats are rotated by the match number so nobody always leads off
+async fn play_match(config: &Config, rules: &Ruleset, match_number: usize) -> Option<MatchOutcome> {
+    let seed = config.seed.wrapping_add(match_number as u64);
+    let mut game = GameState::new(match_number, PlayerPool::new(), 0, config.settings(rules));
+    game.reseed(seed);
+
+    let seat_count = config.players.len();
+    let mut seats = HashMap::new();
+    for offset in 0..seat_count {
+        let seat = (match_number + offset) % seat_count;
+        let difficulty = config.players[seat];
+        let bot_id = game.add_bot(0, difficulty).await.ok()?;
+        let strategy_seed = seed.wrapping_mul(31).wrapping_add(seat as u64);
+        game.bots.insert(bot_id, difficulty.create_reproducible_strategy(strategy_seed));
+        seats.insert(bot_id, seat);
+    }
+
+    let mut rounds = 0;
+    let mut turns = 0;
+    while game.finished_matches.is_empty() && turns < MAX_TURNS_PER_MATCH {
+        if !game.round_in_progress {
+            game.start_round().await;
+            rounds += 1;
+        }
+        if !game.play_bot_turn().await {
+            return None;
+        }
+        turns += 1;
+    }
+
+    let result = game.finished_matches.first()?;
+    let winning_score = result.players
+        .iter()
+        .find(|player| player.id == result.winner_id)
+        .map(|player| player.score)?;
+    Some(MatchOutcome {
+        winner_seat: seats[&result.winner_id],
+        winning_score,
+        rounds,
+        turns,
+    })
+}
+
+fn print_report(config: &Config, rules: &Ruleset, outcomes: &[MatchOutcome]) {
+    println!("Rules: {}", describe_rules(rules));
+    let finished = outcomes.len();
+    println!("  matches: {} ({} unfinished)", finished, config.games - finished);
+    if finished == 0 {
+        return;
+    }
+
+    let average = |total: usize| (total as f64) / (finished as f64);
+    println!(
+        "  avg rounds per match: {:.2}, avg turns per match: {:.1}, avg turns per round: {:.1}",
+        average(outcomes.iter().map(|outcome| outcome.rounds).sum()),
+        average(outcomes.iter().map(|outcome| outcome.turns).sum()),
+        (outcomes.iter().map(|outcome| outcome.turns).sum::<usize>() as f64) /
+            (outcomes.iter().map(|outcome| outcome.rounds).sum::<usize>() as f64)
+    );
+    println!(
+        "  avg winning score: {:.1}",
+        average(outcomes.iter().map(|outcome| outcome.winning_score as usize).sum())
+    );
+    for (seat, difficulty) in config.players.iter().enumerate() {
+        let wins = outcomes
+            .iter()
+            .filter(|outcome| outcome.winner_seat == seat)
+            .count();
+        println!(
+            "  seat {} ({}): {} wins, {:.1}%",
+            seat + 1,
+            difficulty.label(),
+            wins,
+            ((wins as f64) * 100.0) / (finished as f64)
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = match parse_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    set_game_logging(false);
+
+    let players: Vec<&str> = config.players
+        .iter()
+        .map(|difficulty| difficulty.label())
+        .collect();
+    println!(
+        "Simulating {} matches per rule variant, seed {}, players: {}, score target {}\n",
+        config.games,
+        config.seed,
+        players.join(" vs "),
+        config.score_target
+    );
+
+    for rules in &config.variants {
+        let mut outcomes = Vec::new();
+        for match_number in 0..config.games {
+            if let Some(outcome) = play_match(&config, rules, match_number).await {
+                outcomes.push(outcome);
+            }
+        }
+        print_report(&config, rules, &outcomes);
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args_defaults_to_one_variant_per_rule() {
+        let config = parse_args(&[]).unwrap();
+        assert_eq!(config.variants.len(), RULE_NAMES.len() + 1);
+        assert_eq!(describe_rules(&config.variants[0]), "none");
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let config = parse_args(&args("--games 5 --players hard,easy,easy --rules multi_card_play,draw_until_playable")).unwrap();
+        assert_eq!(config.games, 5);
+        assert_eq!(config.players, vec![BotDifficulty::Hard, BotDifficulty::Easy, BotDifficulty::Easy]);
+        assert_eq!(describe_rules(&config.variants[0]), "multi_card_play, draw_until_playable");
+
+        assert!(parse_args(&args("--players hard")).is_err());
+        assert!(parse_args(&args("--rules house_rule")).is_err());
+        assert!(parse_args(&args("--games")).is_err());
+        assert_eq!(parse_args(&args("--hand-size 0")).unwrap_err(), "Hand size must be between 1 and 10");
+        assert!(parse_args(&args("--score-target 9000")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_matches_repeat() {
+        set_game_logging(false);
+        let config = parse_args(&args("--players normal,easy --score-target 0")).unwrap();
+        let rules = Ruleset::default();
+        let first = play_match(&config, &rules, 3).await.unwrap();
+        let second = play_match(&config, &rules, 3).await.unwrap();
+        assert_eq!(first.turns, second.turns);
+        assert_eq!(first.winner_seat, second.winner_seat);
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{ Deserialize, Serializer, Serialize };
 use serde::de::{ self, Deserializer, Visitor };
-use std::fmt;
+use std::{ fmt, str::FromStr };
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Color {
@@ -75,6 +75,39 @@ pub struct Card {
     pub value: Value,
 }
 
+impl Card {
+    //wilds go on anything, everything else has to match the top card's color or value
+    pub fn can_be_played_on(&self, top_card: &Card) -> bool {
+        match self.value {
+            Value::Wild | Value::WildDrawFour => true,
+            _ => self.color == top_card.color || self.value == top_card.value,
+        }
+    }
+
+    pub fn is_wild(&self) -> bool {
+        self.value == Value::Wild || self.value == Value::WildDrawFour
+    }
+
+    //the id Deck::new gives this card: 13 per color in deck order, then the wilds
+    fn standard_id(value: &Value, color: &Color) -> usize {
+        let color_index = match color {
+            Color::Red => 0,
+            Color::Yellow => 1,
+            Color::Green => 2,
+            Color::Blue => 3,
+            Color::Wild => 0,
+        };
+        match value {
+            Value::Number(n) => color_index * 13 + (*n as usize) + 1,
+            Value::Skip => color_index * 13 + 11,
+            Value::Reverse => color_index * 13 + 12,
+            Value::DrawTwo => color_index * 13 + 13,
+            Value::Wild => 53,
+            Value::WildDrawFour => 54,
+        }
+    }
+}
+
 impl Serialize for Value {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
         match self {
@@ -87,3 +120,101 @@ impl Serialize for Value {
         }
     }
 }
+
+// Short notation: a color letter and a value - R5, GS (skip), BR (reverse), YD2 (draw two) - or
+// W and WD4 for wilds, with the chosen color after a colon once played (WD4:r)
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color = match self.color {
+            Color::Red => "R",
+            Color::Yellow => "Y",
+            Color::Green => "G",
+            Color::Blue => "B",
+            Color::Wild => "",
+        };
+        match self.value {
+            Value::Wild | Value::WildDrawFour => {
+                write!(f, "{}", if self.value == Value::Wild { "W" } else { "WD4" })?;
+                if !color.is_empty() {
+                    write!(f, ":{}", color.to_lowercase())?;
+                }
+                Ok(())
+            }
+            Value::Number(n) => write!(f, "{}{}", color, n),
+            Value::Skip => write!(f, "{}S", color),
+            Value::Reverse => write!(f, "{}R", color),
+            Value::DrawTwo => write!(f, "{}D2", color),
+        }
+    }
+}
+
+impl FromStr for Card {
+    type Err = String;
+
+    fn from_str(notation: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid card: {}", notation);
+        //letters may be given in either case
+        let token = notation.to_uppercase();
+        let parse_color = |letter: &str| match letter {
+            "R" => Ok(Color::Red),
+            "Y" => Ok(Color::Yellow),
+            "G" => Ok(Color::Green),
+            "B" => Ok(Color::Blue),
+            _ => Err(invalid()),
+        };
+
+        let (card, chosen_color) = token.split_once(':').unwrap_or((&token, ""));
+        let (color, value) = match card {
+            "W" => (Color::Wild, Value::Wild),
+            "WD4" => (Color::Wild, Value::WildDrawFour),
+            _ if !chosen_color.is_empty() => {
+                return Err(invalid());
+            }
+            _ => {
+                let (color, value) = card.split_at_checked(1).ok_or_else(invalid)?;
+                let value = match value {
+                    "S" => Value::Skip,
+                    "R" => Value::Reverse,
+                    "D2" => Value::DrawTwo,
+                    _ if value.len() == 1 => Value::Number(value.parse().map_err(|_| invalid())?),
+                    _ => {
+                        return Err(invalid());
+                    }
+                };
+                (parse_color(color)?, value)
+            }
+        };
+        let color = if token.contains(':') { parse_color(chosen_color)? } else { color };
+        Ok(Card { id: Card::standard_id(&value, &color), color, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::Deck;
+
+    #[test]
+    fn test_notation_round_trips_the_deck() {
+        for card in Deck::new().cards {
+            assert_eq!(card.to_string().parse::<Card>(), Ok(card));
+        }
+    }
+
+    #[test]
+    fn test_notation() {
+        let played: Card = "WD4:r".parse().unwrap();
+        assert_eq!(played, Card { id: 54, color: Color::Red, value: Value::WildDrawFour });
+        assert_eq!(played.to_string(), "WD4:r");
+        assert_eq!("YD2".parse::<Card>().unwrap().value, Value::DrawTwo);
+        assert_eq!("GS".parse::<Card>().unwrap().to_string(), "GS");
+        assert_eq!("BR".parse::<Card>().unwrap().value, Value::Reverse);
+        for (lower, upper) in [("r5", "R5"), ("rs", "RS"), ("w", "W"), ("wd4", "WD4"), ("wd4:R", "WD4:r")] {
+            assert_eq!(lower.parse::<Card>().unwrap().to_string(), upper);
+        }
+
+        for invalid in ["", "R", "R10", "X5", "R5:g", "W:x", "WD4:", "D2"] {
+            assert!(invalid.parse::<Card>().is_err(), "{} should not parse", invalid);
+        }
+    }
+}
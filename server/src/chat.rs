@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+
+use serde::Serialize;
+
+pub const CHAT_HISTORY_LENGTH: usize = 50;
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 200;
+const RATE_LIMIT_MESSAGES: usize = 5;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
+
+// Words that get starred out before a message is passed on
+const FILTERED_WORDS: &[&str] = &["fuck", "shit", "cunt", "bitch", "asshole", "bastard"];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ChatMessage {
+    pub player_id: usize,
+    pub name: String,
+    pub text: String,
+    pub timestamp: u64, // seconds since the unix epoch
+}
+
+impl ChatMessage {
+    pub fn new(player_id: usize, name: String, text: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        Self { player_id, name, text, timestamp }
+    }
+}
+
+// The last CHAT_HISTORY_LENGTH messages, sent to players when they join
+#[derive(Debug, Default)]
+pub struct ChatHistory {
+    pub messages: VecDeque<ChatMessage>,
+}
+
+impl ChatHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        if self.messages.len() == CHAT_HISTORY_LENGTH {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+}
+
+// Per-connection limit of RATE_LIMIT_MESSAGES messages in any RATE_LIMIT_WINDOW
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    sent: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self) -> Result<(), &'static str> {
+        let now = Instant::now();
+        while self.sent.front().is_some_and(|sent| now.duration_since(*sent) >= RATE_LIMIT_WINDOW) {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= RATE_LIMIT_MESSAGES {
+            return Err("You are sending messages too quickly");
+        }
+        self.sent.push_back(now);
+        Ok(())
+    }
+}
+
+//trim the message, enforce the length limit and star out filtered words
+pub fn sanitize_message(text: &str) -> Result<String, &'static str> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Message is empty");
+    }
+    if text.chars().count() > MAX_CHAT_MESSAGE_LENGTH {
+        return Err("Message is too long");
+    }
+
+    let words = text
+        .split_whitespace()
+        .map(|word| {
+            let bare_word = word
+                .trim_matches(|c: char| !c.is_alphanumeric())
+                .to_lowercase();
+            if FILTERED_WORDS.contains(&bare_word.as_str()) {
+                "*".repeat(word.chars().count())
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<String>>();
+    Ok(words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_message() {
+        assert_eq!(sanitize_message("  hello  there "), Ok("hello there".to_string()));
+        assert_eq!(sanitize_message("oh SHIT!"), Ok("oh *****".to_string()));
+        assert_eq!(sanitize_message("shitake"), Ok("shitake".to_string()));
+        assert!(sanitize_message("   ").is_err());
+        assert!(sanitize_message(&"a".repeat(MAX_CHAT_MESSAGE_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_chat_history_keeps_last_messages() {
+        let mut history = ChatHistory::new();
+        for i in 0..CHAT_HISTORY_LENGTH + 5 {
+            history.push(ChatMessage::new(1, "Player 1".to_string(), i.to_string()));
+        }
+        assert_eq!(history.messages.len(), CHAT_HISTORY_LENGTH);
+        assert_eq!(history.messages.front().unwrap().text, "5");
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new();
+        for _ in 0..RATE_LIMIT_MESSAGES {
+            assert!(limiter.check().is_ok());
+        }
+        assert!(limiter.check().is_err());
+    }
+}
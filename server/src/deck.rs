@@ -1,4 +1,4 @@
-use rand::seq::SliceRandom;
+use rand::{ seq::SliceRandom, Rng };
 
 use crate::card::{ Card, Color };
 use crate::card::Value;
@@ -8,6 +8,12 @@ pub struct Deck {
     pub cards: Vec<Card>,
 }
 
+impl Default for Deck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Deck {
     pub fn new() -> Self {
         let mut cards = Vec::new();
@@ -55,9 +61,8 @@ impl Deck {
         Self { cards }
     }
 
-    pub fn shuffle(&mut self) {
-        let mut rng = rand::thread_rng();
-        self.cards.shuffle(&mut rng);
+    pub fn shuffle(&mut self, rng: &mut impl Rng) {
+        self.cards.shuffle(rng);
     }
 
     pub fn draw(&mut self) -> Option<Card> {
@@ -0,0 +1,281 @@
+use futures::executor::block_on;
+
+use crate::{
+    card::{ Card, Color, Value },
+    game_settings::{ GameSettings, Ruleset },
+    game_state::{ GameState, MAX_PLAYERS, MIN_PLAYERS },
+    player::Player,
+    playerpool::PlayerPool,
+    strategy::{ BotDifficulty, PlayerView },
+};
+
+// 13 kinds per color (0-9, skip, reverse, draw two), then wild and wild draw four
+pub const CARD_KINDS: usize = 54;
+// a colored card, a wild or a wild draw four with each chosen color, or drawing
+pub const ACTION_COUNT: usize = 52 + 4 + 4 + 1;
+pub const DRAW_ACTION: usize = ACTION_COUNT - 1;
+// hand (count per kind), top card (one-hot kind), active color (one-hot), opponent card counts in
+// turn order (padded to the largest table), direction, deck size
+pub const OBSERVATION_SIZE: usize = CARD_KINDS + CARD_KINDS + 4 + (MAX_PLAYERS - 1) + 2;
+
+const COLORS: [Color; 4] = [Color::Red, Color::Yellow, Color::Green, Color::Blue];
+const AGENT_ID: usize = 0;
+
+pub type Observation = [f32; OBSERVATION_SIZE];
+
+fn color_index(color: &Color) -> Option<usize> {
+    COLORS.iter().position(|candidate| candidate == color)
+}
+
+pub fn card_kind(card: &Card) -> usize {
+    let value_index = match card.value {
+        Value::Number(n) => n as usize,
+        Value::Skip => 10,
+        Value::Reverse => 11,
+        Value::DrawTwo => 12,
+        Value::Wild => {
+            return 52;
+        }
+        Value::WildDrawFour => {
+            return 53;
+        }
+    };
+    color_index(&card.color).unwrap_or_default() * 13 + value_index
+}
+
+//the action that plays this card - for wilds, with the color it carries
+pub fn card_action(card: &Card) -> usize {
+    match card.value {
+        Value::Wild => 52 + color_index(&card.color).unwrap_or_default(),
+        Value::WildDrawFour => 56 + color_index(&card.color).unwrap_or_default(),
+        _ => card_kind(card),
+    }
+}
+
+pub fn encode_observation(view: &PlayerView) -> Observation {
+    let mut observation = [0.0; OBSERVATION_SIZE];
+    for card in &view.hand {
+        observation[card_kind(card)] += 1.0;
+    }
+    observation[CARD_KINDS + card_kind(&view.top_card)] = 1.0;
+    if let Some(color) = color_index(&view.active_color) {
+        observation[2 * CARD_KINDS + color] = 1.0;
+    }
+    let opponents_start = 2 * CARD_KINDS + 4;
+    for (slot, opponent) in view.opponents.iter().enumerate() {
+        observation[opponents_start + slot] = opponent.card_count as f32;
+    }
+    observation[OBSERVATION_SIZE - 2] = view.direction as f32;
+    observation[OBSERVATION_SIZE - 1] = view.deck_size as f32;
+    observation
+}
+
+// A gym-style environment over the server's own GameState: the agent plays one round against bots.
+// Every action plays a single card (or draws), whatever the multi card rule says. The reward is 1
+// for winning the round, -1 for losing it and 0 until then
+#[derive(Debug)]
+pub struct Environment {
+    opponents: Vec<BotDifficulty>, // one to MAX_PLAYERS - 1 of them, checked by new
+    pub hand_size: usize,
+    pub rules: Ruleset,
+    game: Option<GameState>,
+}
+
+impl Environment {
+    pub fn new(opponents: Vec<BotDifficulty>, rules: Ruleset) -> Result<Self, String> {
+        if opponents.len() < MIN_PLAYERS - 1 || opponents.len() > MAX_PLAYERS - 1 {
+            return Err(format!("Between {} and {} opponents are needed", MIN_PLAYERS - 1, MAX_PLAYERS - 1));
+        }
+        Ok(Self {
+            opponents,
+            hand_size: GameSettings::default().hand_size,
+            rules,
+            game: None,
+        })
+    }
+
+    pub fn opponents(&self) -> &[BotDifficulty] {
+        &self.opponents
+    }
+
+    //deal a new round. The seed decides the shuffle, the bots' choices and which seat the agent gets
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        let settings = GameSettings {
+            max_players: self.opponents.len() + 1,
+            hand_size: self.hand_size,
+            score_target: 0,
+            rules: self.rules.clone(),
+            ..GameSettings::default()
+        };
+        let mut game = GameState::new(0, PlayerPool::new(), AGENT_ID, settings);
+        game.reseed(seed);
+
+        //the agent has no socket either; it just isn't given a strategy
+        let mut agent = Player::new(AGENT_ID);
+        agent.current_game = Some(game.id);
+        game.game_player_pool.register_bot(agent);
+        for (seat, difficulty) in self.opponents.iter().enumerate() {
+            let bot_id = block_on(game.add_bot(AGENT_ID, *difficulty)).unwrap();
+            let strategy_seed = seed.wrapping_mul(31).wrapping_add(seat as u64);
+            game.bots.insert(bot_id, difficulty.create_reproducible_strategy(strategy_seed));
+        }
+        let agent_seat = (seed as usize) % (self.opponents.len() + 1);
+        let agent = game.game_player_pool.connections.remove(0);
+        game.game_player_pool.connections.insert(agent_seat, agent);
+
+        block_on(game.start_round());
+        self.game = Some(game);
+        self.play_opponents();
+        self.observation()
+    }
+
+    pub fn step(&mut self, action: usize) -> Result<(Observation, f32, bool), &'static str> {
+        if !self.legal_action_mask().get(action).copied().unwrap_or_default() {
+            return Err("Illegal action");
+        }
+        let game = self.game.as_mut().ok_or("Environment has not been reset")?;
+        let result = if action == DRAW_ACTION {
+            block_on(game.draw_for_turn(AGENT_ID))
+        } else {
+            let hand = &game.game_player_pool.get_player_by_id(AGENT_ID).unwrap().hand;
+            let mut card = hand
+                .iter()
+                .find(|card| action_plays(action, card))
+                .cloned()
+                .ok_or("Card not in hand")?;
+            if card.is_wild() {
+                card.color = COLORS[(action - 52) % 4].clone();
+            }
+            block_on(game.play_cards(AGENT_ID, vec![card]))
+        };
+        result?;
+
+        self.play_opponents();
+        let (reward, done) = match self.winner() {
+            Some(AGENT_ID) => (1.0, true),
+            Some(_) => (-1.0, true),
+            None => (0.0, false),
+        };
+        Ok((self.observation(), reward, done))
+    }
+
+    pub fn legal_action_mask(&self) -> [bool; ACTION_COUNT] {
+        let mut mask = [false; ACTION_COUNT];
+        let Some(game) = &self.game else {
+            return mask;
+        };
+        if !game.round_in_progress || game.player_to_play != AGENT_ID {
+            return mask;
+        }
+        mask[DRAW_ACTION] = true;
+        let top_card = game.discard_pile.last().unwrap();
+        let hand = game.game_player_pool.get_player_by_id(AGENT_ID).unwrap().hand;
+        for card in hand.iter().filter(|card| card.can_be_played_on(top_card)) {
+            if card.is_wild() {
+                let first = card_action(&Card { color: Color::Red, ..card.clone() });
+                mask[first..first + 4].fill(true);
+            } else {
+                mask[card_kind(card)] = true;
+            }
+        }
+        mask
+    }
+
+    pub fn observation(&self) -> Observation {
+        self.game
+            .as_ref()
+            .and_then(|game| game.player_view(AGENT_ID))
+            .map(|view| encode_observation(&view))
+            .unwrap_or([0.0; OBSERVATION_SIZE])
+    }
+
+    fn winner(&self) -> Option<usize> {
+        let game = self.game.as_ref()?;
+        game.finished_matches.last().map(|result| result.winner_id)
+    }
+
+    //the bots move until it is the agent's turn again or the round is over
+    fn play_opponents(&mut self) {
+        let Some(game) = self.game.as_mut() else {
+            return;
+        };
+        while game.round_in_progress && game.player_to_play != AGENT_ID {
+            if !block_on(game.play_bot_turn()) {
+                break;
+            }
+        }
+    }
+}
+
+fn action_plays(action: usize, card: &Card) -> bool {
+    match card.value {
+        Value::Wild => (52..56).contains(&action),
+        Value::WildDrawFour => (56..60).contains(&action),
+        _ => card_kind(card) == action,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn environment() -> Environment {
+        Environment::new(vec![BotDifficulty::Normal, BotDifficulty::Easy], Ruleset::default()).unwrap()
+    }
+
+    #[test]
+    fn test_opponent_count_is_checked() {
+        assert!(Environment::new(Vec::new(), Ruleset::default()).is_err());
+        assert!(Environment::new(vec![BotDifficulty::Easy; MAX_PLAYERS], Ruleset::default()).is_err());
+        let mut env = Environment::new(vec![BotDifficulty::Easy; MAX_PLAYERS - 1], Ruleset::default()).unwrap();
+        assert_eq!(env.opponents().len(), MAX_PLAYERS - 1);
+        //a full table still leaves the deck size in the last slot
+        let observation = env.reset(2);
+        assert_eq!(observation[OBSERVATION_SIZE - 1], env.game.as_ref().unwrap().deck.cards.len() as f32);
+    }
+
+    #[test]
+    fn test_card_actions() {
+        let card = Card { id: 1, color: Color::Yellow, value: Value::Reverse };
+        assert_eq!(card_kind(&card), 24);
+        assert_eq!(card_action(&card), 24);
+        let card = Card { id: 54, color: Color::Blue, value: Value::WildDrawFour };
+        assert_eq!(card_kind(&card), 53);
+        assert_eq!(card_action(&card), 59);
+    }
+
+    #[test]
+    fn test_reset_is_repeatable() {
+        let mut first = environment();
+        let mut second = environment();
+        assert_eq!(first.reset(11), second.reset(11));
+        assert_eq!(first.legal_action_mask(), second.legal_action_mask());
+        assert!(first.legal_action_mask()[DRAW_ACTION]);
+    }
+
+    #[test]
+    fn test_episode_runs_to_the_end() {
+        let mut env = environment();
+        let observation = env.reset(5);
+        let hand_size: f32 = observation[..CARD_KINDS].iter().sum();
+        assert!(hand_size >= 7.0);
+
+        let mut done = false;
+        let mut reward = 0.0;
+        for _ in 0..10_000 {
+            let mask = env.legal_action_mask();
+            //play the first legal card, drawing only when there is none
+            let action = (0..DRAW_ACTION).find(|action| mask[*action]).unwrap_or(DRAW_ACTION);
+            let (_, step_reward, step_done) = env.step(action).unwrap();
+            reward = step_reward;
+            done = step_done;
+            if done {
+                break;
+            }
+        }
+        assert!(done);
+        assert!(reward == 1.0 || reward == -1.0);
+        assert!(env.legal_action_mask().iter().all(|legal| !legal));
+        assert!(env.step(DRAW_ACTION).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+use serde::Serialize;
+use sha2::{ Digest, Sha256 };
+
+use crate::{ card::Card, deck::Deck };
+
+pub const MAX_ENTROPY_LENGTH: usize = 64;
+
+// Commit-reveal for the shuffle of each round. The hash of a secret server seed is published while
+// the table waits for the round, players may mix in entropy of their own, and the seed is revealed
+// once the round is over. The round's deck order - and every reshuffle of the discard pile - comes
+// from sha256(server seed, entropy...), so anyone can check the server didn't stack the deck
+#[derive(Debug, Clone)]
+pub struct FairShuffle {
+    server_seed: [u8; 32],
+    pub commitment: String, // hex sha256 of the server seed
+    pub client_entropy: Vec<(usize, String)>, // by player id, in the order it was contributed
+}
+
+// Everything needed to recompute a finished round's shuffle, published when it ends
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ShuffleReveal {
+    pub server_seed: String, // hex
+    pub commitment: String,
+    pub client_entropy: Vec<String>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn shuffle_seed(server_seed: &[u8], client_entropy: &[String]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(server_seed);
+    for entropy in client_entropy {
+        //length-prefixed, so ["ab", "c"] and ["a", "bc"] hash differently
+        hasher.update((entropy.len() as u64).to_be_bytes());
+        hasher.update(entropy.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+impl FairShuffle {
+    pub fn new(rng: &mut impl Rng) -> Self {
+        let server_seed: [u8; 32] = rng.gen();
+        Self {
+            commitment: to_hex(&Sha256::digest(server_seed)),
+            server_seed,
+            client_entropy: Vec::new(),
+        }
+    }
+
+    //a player's contribution replaces any they made before
+    pub fn add_entropy(&mut self, player_id: usize, entropy: &str) -> Result<(), String> {
+        if entropy.is_empty() || entropy.chars().count() > MAX_ENTROPY_LENGTH {
+            return Err(format!("Entropy must be between 1 and {} characters", MAX_ENTROPY_LENGTH));
+        }
+        //it has to fit on one line of a game record as it is
+        if entropy.trim() != entropy || entropy.chars().any(char::is_control) {
+            return Err("Entropy may not contain line breaks or surrounding spaces".to_string());
+        }
+        self.client_entropy.retain(|(contributor, _)| *contributor != player_id);
+        self.client_entropy.push((player_id, entropy.to_string()));
+        Ok(())
+    }
+
+    //the contributions in the order they are hashed
+    pub fn entropy(&self) -> Vec<String> {
+        self.client_entropy
+            .iter()
+            .map(|(_, entropy)| entropy.clone())
+            .collect()
+    }
+
+    //the generator the round's shuffles draw from
+    pub fn rng(&self) -> StdRng {
+        StdRng::from_seed(shuffle_seed(&self.server_seed, &self.entropy()))
+    }
+
+    pub fn reveal(&self) -> ShuffleReveal {
+        ShuffleReveal {
+            server_seed: to_hex(&self.server_seed),
+            commitment: self.commitment.clone(),
+            client_entropy: self.entropy(),
+        }
+    }
+}
+
+impl ShuffleReveal {
+    //check the revealed seed against the commitment and redo the round's first shuffle. The deck
+    //is dealt from the end: the start card first, then each seat's hand in turn
+    pub fn recompute_deck(&self) -> Result<Vec<Card>, &'static str> {
+        let server_seed = from_hex(&self.server_seed).ok_or("Server seed is not valid hex")?;
+        if to_hex(&Sha256::digest(&server_seed)) != self.commitment {
+            return Err("Server seed does not match the commitment");
+        }
+        let mut rng = StdRng::from_seed(shuffle_seed(&server_seed, &self.client_entropy));
+        let mut deck = Deck::new();
+        deck.shuffle(&mut rng);
+        Ok(deck.cards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reveal_reproduces_the_shuffle() {
+        let mut fair_shuffle = FairShuffle::new(&mut StdRng::seed_from_u64(1));
+        fair_shuffle.add_entropy(1, "first try").unwrap();
+        fair_shuffle.add_entropy(2, "lucky").unwrap();
+        fair_shuffle.add_entropy(1, "second try").unwrap();
+        assert!(fair_shuffle.add_entropy(2, "").is_err());
+        assert!(fair_shuffle.add_entropy(2, "two\nlines").is_err());
+        assert!(fair_shuffle.add_entropy(2, " padded").is_err());
+
+        let mut deck = Deck::new();
+        deck.shuffle(&mut fair_shuffle.rng());
+
+        let reveal = fair_shuffle.reveal();
+        assert_eq!(reveal.client_entropy, vec!["lucky", "second try"]);
+        assert_eq!(reveal.recompute_deck(), Ok(deck.cards));
+    }
+
+    #[test]
+    fn test_tampered_seed_is_rejected() {
+        let mut reveal = FairShuffle::new(&mut StdRng::seed_from_u64(1)).reveal();
+        reveal.server_seed.replace_range(0..2, if reveal.server_seed.starts_with("00") { "01" } else { "00" });
+        assert!(reveal.recompute_deck().is_err());
+    }
+
+    #[test]
+    fn test_entropy_changes_the_shuffle() {
+        let mut fair_shuffle = FairShuffle::new(&mut StdRng::seed_from_u64(1));
+        let without = fair_shuffle.reveal().recompute_deck().unwrap();
+        fair_shuffle.add_entropy(1, "x").unwrap();
+        assert_ne!(fair_shuffle.reveal().recompute_deck().unwrap(), without);
+    }
+}
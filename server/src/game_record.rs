@@ -0,0 +1,300 @@
+use std::{ fmt, str::FromStr };
+
+use crate::{
+    card::Card,
+    game_state_events::{ EventLog, GameEvent },
+    strategy::BotAction,
+};
+
+// One match written down a line at a time, in card notation:
+//
+//   # comments and blank lines are skipped
+//   seed 42
+//   seat 17 Normal Bot 1
+//   seat 23 Alice
+//   round
+//   entropy my lucky socks
+//   play 17 R5 Y5
+//   draw 23
+//   play 17 WD4:g
+//
+// The seats are in turn order as the first round was dealt. Each round lists the entropy players
+// mixed into its shuffle, then the moves in the order they were made. Together with the seed that
+// is everything the round's shuffles were drawn from; a draw stands for the whole draw action,
+// however many cards the rules had the player take
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub seed: u64,
+    pub seats: Vec<RecordedSeat>,
+    pub rounds: Vec<RecordedRound>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedSeat {
+    pub player_id: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecordedRound {
+    pub entropy: Vec<String>,
+    pub moves: Vec<RecordedMove>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedMove {
+    pub player_id: usize,
+    pub action: BotAction,
+}
+
+impl GameRecord {
+    //write down the match in an event log. Draws the player whose turn it is didn't choose - the
+    //penalty of a draw two or wild draw four - are left out
+    pub fn from_events(log: &EventLog) -> Option<GameRecord> {
+        let mut seed = None;
+        let mut seats = Vec::new();
+        let mut rounds: Vec<RecordedRound> = Vec::new();
+        let mut player_to_play = None;
+        let mut drawing = false; // the player to play has drawn this turn
+
+        for logged in log.events() {
+            match &logged.event {
+                GameEvent::Seeded { seed: new_seed } if rounds.is_empty() => {
+                    seed = Some(*new_seed);
+                }
+                GameEvent::Dealt { seats: dealt, entropy, player_to_play: first, .. } => {
+                    if seats.is_empty() {
+                        seats = dealt
+                            .iter()
+                            .map(|seat| RecordedSeat { player_id: seat.player_id, name: seat.name.clone() })
+                            .collect();
+                    }
+                    rounds.push(RecordedRound { entropy: entropy.clone(), moves: Vec::new() });
+                    player_to_play = Some(*first);
+                    drawing = false;
+                }
+                GameEvent::TurnPassed { player_id } => {
+                    player_to_play = Some(*player_id);
+                    drawing = false;
+                }
+                GameEvent::CardDrawn { player_id, .. } if Some(*player_id) == player_to_play && !drawing => {
+                    drawing = true;
+                    rounds.last_mut()?.moves.push(RecordedMove { player_id: *player_id, action: BotAction::Draw });
+                }
+                GameEvent::CardsPlayed { player_id, cards } => {
+                    drawing = false;
+                    rounds.last_mut()?.moves.push(RecordedMove {
+                        player_id: *player_id,
+                        action: BotAction::Play(cards.clone()),
+                    });
+                }
+                GameEvent::MatchWon { .. } => {
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        Some(GameRecord { seed: seed?, seats, rounds })
+    }
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "seed {}", self.seed)?;
+        for seat in &self.seats {
+            writeln!(f, "seat {} {}", seat.player_id, seat.name)?;
+        }
+        for round in &self.rounds {
+            writeln!(f, "round")?;
+            for entropy in &round.entropy {
+                writeln!(f, "entropy {}", entropy)?;
+            }
+            for recorded in &round.moves {
+                match &recorded.action {
+                    BotAction::Play(cards) => {
+                        write!(f, "play {}", recorded.player_id)?;
+                        for card in cards {
+                            write!(f, " {}", card)?;
+                        }
+                        writeln!(f)?;
+                    }
+                    BotAction::Draw => writeln!(f, "draw {}", recorded.player_id)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GameRecord {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut seed = None;
+        let mut seats: Vec<RecordedSeat> = Vec::new();
+        let mut rounds: Vec<RecordedRound> = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {}", index + 1, message);
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let rest = line[keyword.len()..].trim();
+            let mut player_id = || -> Result<usize, String> {
+                words
+                    .next()
+                    .and_then(|id| id.parse().ok())
+                    .ok_or_else(|| error("expected a player id"))
+            };
+
+            match keyword {
+                "seed" => {
+                    if seed.is_some() {
+                        return Err(error("seed given twice"));
+                    }
+                    seed = Some(rest.parse().map_err(|_| error("invalid seed"))?);
+                }
+                "seat" => {
+                    let player_id = player_id()?;
+                    if !rounds.is_empty() {
+                        return Err(error("seats must come before the rounds"));
+                    }
+                    if seats.iter().any(|seat| seat.player_id == player_id) {
+                        return Err(error("player seated twice"));
+                    }
+                    //the name is the rest of the line, spacing and all
+                    let name = rest
+                        .split_once(char::is_whitespace)
+                        .map_or(String::new(), |(_, name)| name.trim_start().to_string());
+                    seats.push(RecordedSeat { player_id, name });
+                }
+                "round" => {
+                    if !rest.is_empty() {
+                        return Err(error("round takes nothing after it"));
+                    }
+                    rounds.push(RecordedRound::default());
+                }
+                "entropy" => {
+                    let round = rounds.last_mut().ok_or_else(|| error("entropy outside a round"))?;
+                    if !round.moves.is_empty() {
+                        return Err(error("entropy must come before the round's moves"));
+                    }
+                    round.entropy.push(rest.to_string());
+                }
+                "play" | "draw" => {
+                    let player_id = player_id()?;
+                    if !seats.iter().any(|seat| seat.player_id == player_id) {
+                        return Err(error("move by a player without a seat"));
+                    }
+                    let action = if keyword == "draw" {
+                        if words.next().is_some() {
+                            return Err(error("draw takes no cards"));
+                        }
+                        BotAction::Draw
+                    } else {
+                        let cards = words
+                            .map(|card| card.parse::<Card>().map_err(|err| error(&err)))
+                            .collect::<Result<Vec<Card>, String>>()?;
+                        if cards.is_empty() {
+                            return Err(error("play needs at least one card"));
+                        }
+                        BotAction::Play(cards)
+                    };
+                    let round = rounds.last_mut().ok_or_else(|| error("move outside a round"))?;
+                    round.moves.push(RecordedMove { player_id, action });
+                }
+                _ => {
+                    return Err(error(&format!("unknown entry: {}", keyword)));
+                }
+            }
+        }
+
+        Ok(GameRecord {
+            seed: seed.ok_or("missing seed")?,
+            seats,
+            rounds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        game_settings::GameSettings,
+        game_state::GameState,
+        player::Player,
+        playerpool::PlayerPool,
+        strategy::BotDifficulty,
+    };
+    use tokio::sync::mpsc;
+
+    const RECORD: &str =
+        "# a short game\nseed 42\nseat 17 Normal Bot 1\nseat 23 Ann  Lee\n\nround\nentropy my  lucky socks\nplay 17 R5 Y5\ndraw 23\nplay 17 WD4:g\nround\ndraw 17\n";
+
+    #[test]
+    fn test_record_round_trips() {
+        let record: GameRecord = RECORD.parse().unwrap();
+        assert_eq!(record.seed, 42);
+        assert_eq!(record.seats[0].name, "Normal Bot 1");
+        assert_eq!(record.seats[1].name, "Ann  Lee");
+        assert_eq!(record.rounds.len(), 2);
+        assert_eq!(record.rounds[0].entropy, vec!["my  lucky socks"]);
+        assert_eq!(record.rounds[0].moves.len(), 3);
+        assert_eq!(record.rounds[0].moves[1], RecordedMove { player_id: 23, action: BotAction::Draw });
+
+        let written = record.to_string();
+        assert!(written.starts_with("seed 42\nseat 17 Normal Bot 1\n"));
+        assert!(written.ends_with("play 17 WD4:g\nround\ndraw 17\n"));
+        assert_eq!(written.parse::<GameRecord>(), Ok(record));
+    }
+
+    #[test]
+    fn test_invalid_records_name_the_line() {
+        assert_eq!("seat 1 A".parse::<GameRecord>(), Err("missing seed".to_string()));
+        assert_eq!("seed 1\nround\nplay 2 R5".parse::<GameRecord>(), Err("line 3: move by a player without a seat".to_string()));
+        assert_eq!("seed 1\nseat 2 A\nround\nplay 2 R55".parse::<GameRecord>(), Err("line 4: Invalid card: R55".to_string()));
+        assert_eq!("seed 1\nseat 2 A\ndraw 2".parse::<GameRecord>(), Err("line 3: move outside a round".to_string()));
+        assert!("seed 1\nseat 2 A\nround\ndraw 2\nentropy late".parse::<GameRecord>().is_err());
+        assert!("seed 1\nseat 2 A\nround\ndraw 2 R5".parse::<GameRecord>().is_err());
+        assert!("seed 1\nseed 2".parse::<GameRecord>().is_err());
+        assert!("seed 1\npass 2".parse::<GameRecord>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_written_from_a_played_match() {
+        let mut game = GameState::new(1, PlayerPool::new(), 1, GameSettings::default());
+        game.reseed(9);
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.game_player_pool.register_connection(tx, Player::new(1));
+        game.add_bot(1, BotDifficulty::Normal).await.unwrap();
+        game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+        game.contribute_entropy(1, "shuffle well").await.unwrap();
+        game.remove_player(1).await.unwrap();
+
+        let mut turns = 0;
+        while game.finished_matches.is_empty() && turns < 100_000 {
+            if !game.round_in_progress {
+                game.start_round().await;
+            }
+            assert!(game.play_bot_turn().await);
+            turns += 1;
+        }
+
+        let record = GameRecord::from_events(game.last_match_events.as_ref().unwrap()).unwrap();
+        assert_eq!(record.seed, 9);
+        assert_eq!(record.seats.len(), 2);
+        assert_eq!(record.rounds[0].entropy, vec!["shuffle well"]);
+        //every turn a bot took is one move
+        let moves: usize = record.rounds
+            .iter()
+            .map(|round| round.moves.len())
+            .sum();
+        assert_eq!(moves, turns);
+        assert_eq!(record.to_string().parse::<GameRecord>(), Ok(record));
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{ Deserialize, Serialize };
+
+use crate::game_state::{ MAX_PLAYERS, MIN_PLAYERS };
+
+pub const MAX_NAME_LENGTH: usize = 32; // for game and player names alike
+pub const MAX_HAND_SIZE: usize = 10;
+pub const MAX_SCORE_TARGET: u32 = 5000;
+pub const MAX_HARD_BOT_TIME_MS: u64 = 2000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+// House rules the host can switch on or off when creating a table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ruleset {
+    pub multi_card_play: bool, // several cards of the same value may be played in one turn
+    pub draw_until_playable: bool, // drawing keeps going until a playable card turns up
+    pub draw_cards_skip_turn: bool, // the victim of a draw two / wild draw four also loses their turn
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Self {
+            multi_card_play: true,
+            draw_until_playable: false,
+            draw_cards_skip_turn: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameSettings {
+    pub name: Option<String>,
+    pub max_players: usize,
+    pub hand_size: usize,
+    pub score_target: u32, // 0 makes every round a match of its own
+    pub rules: Ruleset,
+    pub visibility: Visibility,
+    pub rotate_seats: bool, // a rematch starts with the seats rotated by one
+    pub bot_takeover: bool, // a bot plays for anyone who disconnects or keeps running out of time
+    pub hard_bot_time_ms: u64, // how long a hard bot may search for each move; easy and normal bots don't search
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            name: None,
+            max_players: 6,
+            hand_size: 7,
+            score_target: 500,
+            rules: Ruleset::default(),
+            visibility: Visibility::Public,
+            rotate_seats: true,
+            bot_takeover: true,
+            hard_bot_time_ms: 150,
+        }
+    }
+}
+
+impl GameSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.name {
+            let name = name.trim();
+            if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+                return Err(format!("Game name must be between 1 and {} characters", MAX_NAME_LENGTH));
+            }
+        }
+        if self.max_players < MIN_PLAYERS || self.max_players > MAX_PLAYERS {
+            return Err(format!("Max players must be between {} and {}", MIN_PLAYERS, MAX_PLAYERS));
+        }
+        if self.hand_size == 0 || self.hand_size > MAX_HAND_SIZE {
+            return Err(format!("Hand size must be between 1 and {}", MAX_HAND_SIZE));
+        }
+        //0 is a valid target: the match is over after one round
+        if self.score_target > MAX_SCORE_TARGET {
+            return Err(format!("Score target must be at most {}", MAX_SCORE_TARGET));
+        }
+        if self.hard_bot_time_ms == 0 || self.hard_bot_time_ms > MAX_HARD_BOT_TIME_MS {
+            return Err(format!("Hard bot time must be between 1 and {} ms", MAX_HARD_BOT_TIME_MS));
+        }
+        Ok(())
+    }
+
+    pub fn display_name(&self, game_id: usize) -> String {
+        match &self.name {
+            Some(name) => name.trim().to_string(),
+            None => format!("Game {}", game_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_settings_are_valid() {
+        assert!(GameSettings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        let settings = GameSettings { max_players: 11, ..GameSettings::default() };
+        assert!(settings.validate().is_err());
+
+        let settings = GameSettings { hand_size: 0, ..GameSettings::default() };
+        assert!(settings.validate().is_err());
+
+        let settings = GameSettings { name: Some("   ".to_string()), ..GameSettings::default() };
+        assert!(settings.validate().is_err());
+
+        let settings = GameSettings { hard_bot_time_ms: 0, ..GameSettings::default() };
+        assert!(settings.validate().is_err());
+
+        let settings = GameSettings { score_target: 0, ..GameSettings::default() };
+        assert!(settings.validate().is_ok());
+        let settings = GameSettings { score_target: MAX_SCORE_TARGET + 1, ..GameSettings::default() };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_settings_deserialize_with_defaults() {
+        let settings: GameSettings = serde_json
+            ::from_str(r#"{"name": "Friday", "rules": {"draw_until_playable": true}}"#)
+            .unwrap();
+        assert_eq!(settings.display_name(1), "Friday");
+        assert_eq!(settings.max_players, 6);
+        assert!(settings.rules.multi_card_play);
+        assert!(settings.rules.draw_until_playable);
+    }
+}
@@ -1,12 +1,38 @@
+use std::{ collections::HashMap, sync::PoisonError, time::{ Instant, SystemTime, UNIX_EPOCH } };
+
+use rand::{ rngs::{ OsRng, StdRng }, Rng, RngCore, SeedableRng };
+use serde::Serialize;
 use serde_json::json;
 
 use crate::{
     card::{ Card, Value },
+    chat::ChatHistory,
+    player::{ Player, SerializablePlayer },
     playerpool::PlayerPool,
     websocket::create_websocket_message,
     deck::Deck,
+    fair_shuffle::{ FairShuffle, ShuffleReveal },
+    game_settings::GameSettings,
+    game_state_rematch::RematchVote,
+    game_state_events::{ DealtSeat, EventLog, GameEvent },
+    strategy::SharedStrategy,
 };
 
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 10;
+pub const MAX_SPECTATORS: usize = 10;
+
+// Outcome of a finished match, kept in the lobby's archive after the table is gone
+#[derive(Serialize, Clone, Debug)]
+pub struct MatchResult {
+    pub game_id: usize,
+    pub name: String,
+    pub winner_id: usize,
+    pub players: Vec<SerializablePlayer>,
+    pub finished_at: u64, // seconds since the unix epoch
+    pub seed: u64, // the table's seed for this match, to reproduce it in a bug report
+}
+
 #[derive(Debug)]
 pub struct GameState {
     pub id: usize,
@@ -18,18 +44,47 @@ pub struct GameState {
     pub round_in_progress: bool,
     pub is_waiting_for_players: bool,
     pub game_player_pool: PlayerPool,
+    pub host_id: Option<usize>, // the player allowed to start, kick and close the game
+    pub settings: GameSettings,
+    pub invite_code: Option<String>, // private games can only be joined with this code
+    pub password: Option<String>,
+    pub chat_history: ChatHistory,
+    pub last_activity: Instant,
+    pub empty_since: Option<Instant>, // set while nobody is at the table
+    pub finished_matches: Vec<MatchResult>, // waiting to be archived by the lobby
+    pub rematch_vote: Option<RematchVote>,
+    pub is_paused: bool,
+    pub pause_votes: Vec<usize>, // players asking to pause (or, while paused, to resume)
+    pub bots: HashMap<usize, SharedStrategy>, // strategies of the bot players, by player id
+    // the seed of the current match. Together with the entropy players mixed into each round it
+    // reproduces the shuffles; bot decisions only repeat for bots that don't search against the clock
+    pub seed: u64,
+    pub rng: StdRng, // bot ids and strategies, and each round's server seed, draw from here
+    pub fair_shuffle: FairShuffle, // committed to before the next (or current) round, revealed after it
+    pub shuffle_rng: StdRng, // every shuffle during a round draws from here, seeded by fair_shuffle
+    pub last_reveal: Option<ShuffleReveal>, // lets players check the round that just ended
+    pub last_match_events: Option<EventLog>, // the log of the match that just ended
+    pub turn_started_at: Instant,
+    pub missed_turns: HashMap<usize, u32>, // turns in a row each player has let run out
+    pub events: EventLog, // everything that happened to the cards and the turn, in order
 }
 
 impl GameState {
-    pub fn new(id: usize, player_pool: PlayerPool) -> Self {
+    //a table seeded from OS entropy. Use reseed to run it from a known seed instead
+    pub fn new(id: usize, player_pool: PlayerPool, host_id: usize, settings: GameSettings) -> Self {
+        let seed = OsRng.next_u64();
+        let mut rng = StdRng::seed_from_u64(seed);
         let mut deck = Deck::new();
-        deck.shuffle();
+        deck.shuffle(&mut rng);
+        let fair_shuffle = FairShuffle::new(&mut rng);
 
         let discard_pile = vec![deck.draw().unwrap()]; // Draw the initial card
         let direction = 1;
+        let mut events = EventLog::default();
+        events.record(GameEvent::Seeded { seed });
 
         Self {
-            id: id,
+            id,
             player_to_play: 0,
             deck,
             discard_pile,
@@ -37,19 +92,48 @@ impl GameState {
             round_in_progress: false,
             is_waiting_for_players: true,
             game_player_pool: player_pool,
+            host_id: Some(host_id),
+            settings,
+            invite_code: None,
+            password: None,
+            chat_history: ChatHistory::new(),
+            last_activity: Instant::now(),
+            empty_since: Some(Instant::now()),
+            finished_matches: Vec::new(),
+            rematch_vote: None,
+            is_paused: false,
+            pause_votes: Vec::new(),
+            bots: HashMap::new(),
+            seed,
+            rng,
+            shuffle_rng: fair_shuffle.rng(),
+            fair_shuffle,
+            last_reveal: None,
+            last_match_events: None,
+            turn_started_at: Instant::now(),
+            missed_turns: HashMap::new(),
+            events,
         }
     }
 
     pub async fn next_turn(&mut self) {
         //increment the current turn
         let next_player = self.get_next_player();
+        self.give_turn_to(next_player).await;
+    }
+
+    pub async fn give_turn_to(&mut self, next_player: Player) {
+        self.touch();
+        self.turn_started_at = Instant::now();
         //update the next_player's hand for them via the pool connection
         let _ = self.update_single_player(&next_player).await;
         self.player_to_play = next_player.id;
+        self.events.record(GameEvent::TurnPassed { player_id: next_player.id });
         let your_turn_json =
             json!({
                 "player_id": next_player.id,
-                "message": "your turn!"
+                "message": "your turn!",
+                "legal_moves": self.legal_moves(next_player.id),
             }).to_string();
         let message = create_websocket_message("your_turn", &your_turn_json);
         //update the game's player_to_pl
@@ -59,41 +143,161 @@ impl GameState {
         let _ = self.update_game_state().await;
     }
 
-    // HELPER FUNCTIONS
-
-    pub fn check_winner(&self) -> Option<usize> {
-        for entry in &self.game_player_pool.connections {
-            if entry.player.hand.is_empty() && !entry.player.is_spectator {
-                return Some(entry.player.id);
-            }
+    //restart the table's randomness from the given seed, with a fresh deck, e.g. to reproduce a reported game
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.deck = Deck::new();
+        self.deck.shuffle(&mut self.rng);
+        self.discard_pile = vec![self.deck.draw().unwrap()];
+        self.fair_shuffle = FairShuffle::new(&mut self.rng);
+        //bots that roll dice draw from the new seed too, in seat-independent id order
+        let mut bot_ids: Vec<usize> = self.bots.keys().copied().collect();
+        bot_ids.sort_unstable();
+        for bot_id in bot_ids {
+            let strategy_seed = self.rng.gen();
+            self.bots[&bot_id].lock().unwrap_or_else(PoisonError::into_inner).reseed(strategy_seed);
         }
-        None
+        self.events.record(GameEvent::Seeded { seed });
     }
 
+    // HELPER FUNCTIONS
+
     pub fn is_valid_play(&self, card: &Card) -> bool {
         let top_card = self.discard_pile.last().unwrap();
-        match card.value {
-            Value::Wild | Value::WildDrawFour => true,
-            _ => card.color == top_card.color || card.value == top_card.value,
+        card.can_be_played_on(top_card)
+    }
+
+    //record that something happened at the table, holding off the idle timeout
+    pub fn touch(&mut self) {
+        self.last_activity = Instant::now();
+    }
+
+    pub fn is_host(&self, player_id: usize) -> bool {
+        self.host_id == Some(player_id)
+    }
+
+    //only the host can start the game, and only once enough players have joined.
+    //the host may start before everyone has readied up
+    pub async fn start_game(&mut self, player_id: usize) -> Result<(), &'static str> {
+        if !self.is_host(player_id) {
+            return Err("Only the host can start the game");
+        }
+        if self.round_in_progress {
+            return Err("Round already in progress");
+        }
+        if self.rematch_vote.is_some() {
+            return Err("Rematch vote in progress");
         }
+        if self.get_all_players_in_game().len() < MIN_PLAYERS {
+            return Err("Not enough players to start");
+        }
+        self.is_waiting_for_players = false;
+        self.start_round().await;
+        Ok(())
+    }
+
+    //the host pauses or resumes on their own; anyone else adds their vote, and the game pauses or
+    //resumes once every seated player has voted for it
+    pub async fn request_pause(&mut self, player_id: usize, paused: bool) -> Result<(), &'static str> {
+        if !self.round_in_progress {
+            return Err("No round in progress");
+        }
+        if self.is_paused == paused {
+            return Err(if paused { "Game is already paused" } else { "Game is not paused" });
+        }
+        //seats played by bots have no say
+        let seated: Vec<usize> = self.get_all_players_in_game()
+            .iter()
+            .filter(|player| !self.bots.contains_key(&player.id))
+            .map(|player| player.id)
+            .collect();
+        if !seated.contains(&player_id) {
+            return Err("Only seated players can pause the game");
+        }
+
+        if !self.pause_votes.contains(&player_id) {
+            self.pause_votes.push(player_id);
+        }
+        self.touch();
+        let unanimous = seated.iter().all(|id| self.pause_votes.contains(id));
+        if self.is_host(player_id) || unanimous {
+            self.is_paused = paused;
+            self.pause_votes.clear();
+            self.events.record(GameEvent::PauseChanged { paused });
+            self.turn_started_at = Instant::now();
+            let sv = if paused { "game_paused" } else { "game_resumed" };
+            let message = create_websocket_message(sv, &self.id.to_string());
+            self.game_player_pool.broadcast_message(message).await;
+        }
+
+        let _ = self.update_game_state().await;
+        Ok(())
+    }
+
+    //a player marks themselves (un)ready in the waiting room. Once every seated player is ready the round starts
+    pub async fn set_ready(&mut self, player_id: usize, ready: bool) -> Result<(), &'static str> {
+        if self.round_in_progress {
+            return Err("Round already in progress");
+        }
+        if self.rematch_vote.is_some() {
+            return Err("Rematch vote in progress");
+        }
+        let player = self.get_player_by_id_mut(player_id).ok_or("Player not found")?;
+        if player.is_spectator {
+            return Err("Spectators cannot ready up");
+        }
+        player.is_ready = ready;
+        self.touch();
+
+        let _ = self.update_list_of_players().await;
+        self.start_round_if_all_ready().await;
+        Ok(())
     }
 
-    pub async fn check_and_start_round(&mut self) {
-        if self.game_player_pool.connections.len() >= 2 && !self.round_in_progress {
+    //between rounds, deal as soon as every seated player is ready - whether the last of them just
+    //readied up or the last one holding things up left. A table of bots doesn't play on its own
+    pub async fn start_round_if_all_ready(&mut self) {
+        if self.round_in_progress || self.rematch_vote.is_some() || !self.has_human_players() {
+            return;
+        }
+        if self.all_players_ready() {
             self.is_waiting_for_players = false;
-            let _ = self.start_round().await;
+            self.start_round().await;
+        }
+    }
+
+    //a seated player mixes their own entropy into the next round's shuffle
+    pub async fn contribute_entropy(&mut self, player_id: usize, entropy: &str) -> Result<(), String> {
+        if self.round_in_progress {
+            return Err("Round already in progress".to_string());
+        }
+        let player = self.game_player_pool.get_player_by_id(player_id).ok_or("Player not found")?;
+        if player.is_spectator {
+            return Err("Spectators cannot contribute entropy".to_string());
         }
+        self.fair_shuffle.add_entropy(player_id, entropy)?;
+        self.touch();
+        let _ = self.update_game_state().await;
+        Ok(())
+    }
+
+    pub fn all_players_ready(&self) -> bool {
+        let players = self.get_all_players_in_game();
+        players.len() >= MIN_PLAYERS && players.iter().all(|p| p.is_ready)
     }
 
     pub async fn start_round(&mut self) {
         //get a list of players
 
         let players = self.get_all_players_in_game();
-        if players.len() >= 2 {
+        if players.len() >= MIN_PLAYERS {
             self.round_in_progress = true;
             self.direction = 1;
+            //the commitment was published while the table waited; from here on no entropy is taken
+            self.shuffle_rng = self.fair_shuffle.rng();
             self.deck = Deck::new();
-            self.deck.shuffle();
+            self.deck.shuffle(&mut self.shuffle_rng);
             self.reset_played_wild_cards();
             self.discard_pile = vec![self.deck.draw().unwrap()]; // Draw the initial card
 
@@ -105,14 +309,34 @@ impl GameState {
                 self.discard_pile.last().unwrap().value == Value::Reverse ||
                 self.discard_pile.last().unwrap().value == Value::DrawTwo
             {
-                self.shuffle_discard_into_deck();
+                //the rejected card goes to the bottom of the deck so it stays in play
+                let rejected = self.discard_pile.pop().unwrap();
+                self.deck.cards.insert(0, rejected);
                 self.discard_pile = vec![self.deck.draw().unwrap()];
             }
 
             self.player_to_play = players[0].id;
+            self.turn_started_at = Instant::now();
 
             // Dealing cards to players
             self.deal_cards().await;
+            let seats = self.get_all_players_in_game()
+                .into_iter()
+                .map(|player| DealtSeat {
+                    player_id: player.id,
+                    name: player.name,
+                    is_bot: player.is_bot,
+                    score: player.score,
+                    hand: player.hand,
+                })
+                .collect();
+            self.events.record(GameEvent::Dealt {
+                seats,
+                entropy: self.fair_shuffle.entropy(),
+                deck: self.deck.cards.clone(),
+                discard_pile: self.discard_pile.clone(),
+                player_to_play: self.player_to_play,
+            });
 
             // Sending player hands to players
             self.send_player_hands().await;
@@ -123,7 +347,8 @@ impl GameState {
             let your_turn_json =
                 json!({
                 "player_id": self.player_to_play,
-                "message": "your turn!"
+                "message": "your turn!",
+                "legal_moves": self.legal_moves(self.player_to_play),
             }).to_string();
             let message = create_websocket_message("your_turn", &your_turn_json);
             //get the player whose turn it is from the game pool
@@ -134,16 +359,17 @@ impl GameState {
                 self.game_player_pool.send_message(&player, message).await;
             } else {
                 // Handle the case where the player is not found, if needed
-                println!("Player to start was not found");
+                game_log!("Player to start was not found");
             }
         }
     }
 
     pub async fn deal_cards(&mut self) {
         let deck = &mut self.deck;
+        let hand_size = self.settings.hand_size;
         for conn in self.game_player_pool.connections.iter_mut() {
             if !conn.player.is_spectator {
-                let hand = deck.draw_n(7);
+                let hand = deck.draw_n(hand_size);
                 conn.player.set_hand(hand);
             }
         }
@@ -157,16 +383,195 @@ impl GameState {
         }
     }
 
-    pub fn end_round(&mut self) {
+    //the round winner scores the points left in everyone else's hand. Reaching the score target wins the match
+    pub async fn finish_round(&mut self, winner_id: usize) {
+        let points: i32 = self.game_player_pool.connections
+            .iter()
+            .filter(|conn| conn.player.id != winner_id && !conn.player.is_spectator)
+            .flat_map(|conn| conn.player.hand.iter())
+            .map(|card| card.value.to_points())
+            .sum();
+
+        let mut winner_score = 0;
+        if let Some(winner) = self.get_player_by_id_mut(winner_id) {
+            winner.score += points;
+            winner_score = winner.score;
+        }
+        self.events.record(GameEvent::RoundWon { player_id: winner_id, points });
+
+        //create and broadcast a message to all players the id of the winning player
+        let winner_json =
+            json!({
+                "winner_id": winner_id,
+                "points": points,
+                "score": winner_score,
+            }).to_string();
+        let message = create_websocket_message("winner_found", &winner_json);
+        self.game_player_pool.broadcast_message(message).await;
+
+        self.end_round().await;
+
+        let score_target = self.settings.score_target as i32;
+        if score_target == 0 || winner_score >= score_target {
+            let match_result = MatchResult {
+                game_id: self.id,
+                name: self.settings.display_name(self.id),
+                winner_id,
+                players: self.get_all_players_in_game()
+                    .iter()
+                    .map(|player| player.to_serializable())
+                    .collect(),
+                finished_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default(),
+                seed: self.seed,
+            };
+            let match_json = serde_json::to_string(&match_result).unwrap();
+            let message = create_websocket_message("match_won", &match_json);
+            self.game_player_pool.broadcast_message(message).await;
+            self.finished_matches.push(match_result);
+            for conn in &mut self.game_player_pool.connections {
+                conn.player.score = 0;
+            }
+            self.events.record(GameEvent::MatchWon { player_id: winner_id });
+            self.last_match_events = Some(self.events.start_match());
+            //the seed is public now, so the rematch must not be predictable from it
+            self.reseed(OsRng.next_u64());
+            self.start_rematch_vote().await;
+        }
+
+        let _ = self.update_list_of_players().await;
+    }
+
+    //whether the round was won or abandoned, its shuffle is revealed to everyone at the table
+    pub async fn end_round(&mut self) {
         let players = self.get_all_players_in_game();
         for conn in &mut self.game_player_pool.connections {
             conn.player.hand.clear();
+            //bots, and seats a bot is playing, are always ready for the next round
+            conn.player.is_ready = conn.player.is_bot || conn.player.is_bot_controlled;
+        }
+        //reveal the finished round's server seed and commit to the next one
+        if self.round_in_progress {
+            let reveal = self.fair_shuffle.reveal();
+            let reveal_json = serde_json::to_string(&reveal).unwrap();
+            let message = create_websocket_message("shuffle_revealed", &reveal_json);
+            self.game_player_pool.broadcast_message(message).await;
+            self.last_reveal = Some(reveal);
+            self.fair_shuffle = FairShuffle::new(&mut self.rng);
         }
         self.deck = Deck::new();
-        self.deck.shuffle();
+        self.deck.shuffle(&mut self.rng);
         self.round_in_progress = false;
+        self.is_paused = false;
+        self.pause_votes.clear();
+        //players who joined mid-round have been waiting as spectators; seat them for the next one
+        self.promote_waiting_spectators();
         self.discard_pile = vec![self.deck.draw().unwrap()];
-        self.player_to_play = players[0].id;
+        if let Some(first_player) = players.first() {
+            self.player_to_play = first_player.id;
+        }
+        self.events.record(GameEvent::RoundEnded {
+            deck: self.deck.cards.clone(),
+            discard_pile: self.discard_pile.clone(),
+        });
         // Reset game state for next round
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{ player::Player, test_fixtures::game_with_players };
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_round_starts_once_everyone_is_ready() {
+        let mut game = game_with_players(3);
+        assert!(!game.all_players_ready());
+        game.set_ready(1, true).await.unwrap();
+        game.set_ready(2, true).await.unwrap();
+        game.set_ready(2, false).await.unwrap();
+        game.set_ready(2, true).await.unwrap();
+        assert!(!game.round_in_progress);
+
+        game.set_ready(3, true).await.unwrap();
+        assert!(game.round_in_progress);
+        assert_eq!(game.set_ready(1, false).await, Err("Round already in progress"));
+    }
+
+    #[tokio::test]
+    async fn test_ready_check_rules_out_spectators_and_strangers() {
+        let mut game = game_with_players(2);
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.add_spectator(tx, Player::new(3)).await.unwrap();
+
+        assert_eq!(game.set_ready(3, true).await, Err("Spectators cannot ready up"));
+        assert_eq!(game.set_ready(42, true).await, Err("Player not found"));
+        //one ready player is not a table
+        game.remove_player(2).await.unwrap();
+        game.set_ready(1, true).await.unwrap();
+        assert!(!game.round_in_progress);
+    }
+
+    #[tokio::test]
+    async fn test_round_starts_when_the_last_unready_player_leaves() {
+        let mut game = game_with_players(3);
+        game.set_ready(1, true).await.unwrap();
+        game.set_ready(2, true).await.unwrap();
+
+        game.remove_player(3).await.unwrap();
+
+        assert!(game.round_in_progress);
+        assert_eq!(game.get_all_players_in_game().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pause_blocks_play_until_resumed() {
+        let mut game = game_with_players(3);
+        game.start_round().await;
+
+        //player 2 alone cannot pause, but the host can
+        game.request_pause(2, true).await.unwrap();
+        assert!(!game.is_paused);
+        game.request_pause(1, true).await.unwrap();
+        assert!(game.is_paused);
+        assert_eq!(game.draw_for_turn(1).await, Err("Game is paused"));
+
+        //votes from the other players are not enough on their own
+        game.request_pause(2, false).await.unwrap();
+        game.request_pause(3, false).await.unwrap();
+        assert!(game.is_paused);
+        game.request_pause(1, false).await.unwrap();
+        assert!(!game.is_paused);
+        assert!(game.draw_for_turn(1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_round_shuffle_is_revealed_after_the_round() {
+        let mut game = game_with_players(2);
+        let (tx, mut rx) = mpsc::channel::<String>(100);
+        game.game_player_pool.reconnect(2, tx);
+        game.contribute_entropy(2, "my own luck").await.unwrap();
+        let commitment = game.fair_shuffle.commitment.clone();
+        game.start_round().await;
+        assert!(game.contribute_entropy(1, "too late").await.is_err());
+        let deck_after_deal = game.deck.cards.clone();
+
+        game.remove_player(1).await.unwrap();
+
+        //the round was abandoned, not won, and the seed still goes out
+        let mut revealed = false;
+        while let Ok(message) = rx.try_recv() {
+            revealed |= message.contains("shuffle_revealed");
+        }
+        assert!(revealed);
+        let reveal = game.last_reveal.clone().unwrap();
+        assert_eq!(reveal.commitment, commitment);
+        assert_ne!(game.fair_shuffle.commitment, commitment);
+        //dealing takes cards off the end; rejected start cards were put back at the front
+        let recomputed = reveal.recompute_deck().unwrap();
+        let remaining = deck_after_deal.len();
+        assert!((0..remaining).any(|rejected| deck_after_deal[rejected..] == recomputed[..remaining - rejected]));
+    }
+}
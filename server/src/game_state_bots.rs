@@ -0,0 +1,456 @@
+use std::{ collections::HashMap, sync::PoisonError, time::{ Duration, Instant } };
+
+use rand::Rng;
+use serde_json::json;
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    game_state::GameState,
+    game_state_events::GameEvent,
+    player::Player,
+    strategy::{ BotAction, BotDifficulty, HeuristicStrategy, Miss, OpponentView, PlayerView, SharedStrategy, Strategy },
+    websocket::create_websocket_message,
+};
+
+// how long a bot "thinks" before making its move, so people can follow along
+pub const BOT_MOVE_DELAY: Duration = Duration::from_millis(1200);
+// how long a player has for their turn before a bot moves for them
+pub const AFK_TURN_TIMEOUT: Duration = Duration::from_secs(30);
+// after this many timed out turns in a row a bot takes over the seat
+pub const AFK_TIMEOUT_LIMIT: u32 = 2;
+
+// A bot's move, taken off the table so it can be worked out without holding the lobby lock. It is
+// only made if nothing has happened at the table in the meantime
+pub struct BotTurn {
+    pub game_id: usize,
+    pub bot_id: usize,
+    pub turn: u64, // seq the game's event log had reached when the view was taken
+    pub view: PlayerView,
+    pub strategy: SharedStrategy,
+}
+
+impl BotTurn {
+    pub fn choose_action(&self) -> BotAction {
+        self.strategy.lock().unwrap_or_else(PoisonError::into_inner).choose_action(&self.view)
+    }
+}
+
+// computer players: they sit in the game's player pool without a socket, and their strategy lives here
+impl GameState {
+    pub async fn add_bot(
+        &mut self,
+        host_id: usize,
+        difficulty: BotDifficulty
+    ) -> Result<usize, &'static str> {
+        if !self.is_host(host_id) {
+            return Err("Only the host can add bots");
+        }
+        if self.round_in_progress {
+            return Err("Bots can only be added between rounds");
+        }
+        if self.get_all_players_in_game().len() >= self.settings.max_players {
+            return Err("Game is full");
+        }
+
+        let mut bot_id: usize = self.rng.gen();
+        while self.game_player_pool.get_player_by_id(bot_id).is_some() {
+            bot_id = self.rng.gen();
+        }
+        let bot_number = self.game_player_pool.connections
+            .iter()
+            .filter(|conn| conn.player.is_bot)
+            .count() + 1;
+
+        let mut bot = Player::new(bot_id);
+        bot.name = self.unique_name(&format!("{} Bot {}", difficulty.label(), bot_number), bot_id);
+        bot.is_bot = true;
+        bot.is_ready = true;
+        bot.current_game = Some(self.id);
+        self.game_player_pool.register_bot(bot);
+        let strategy_seed = self.rng.gen();
+        self.bots.insert(bot_id, difficulty.create_strategy(strategy_seed, &self.settings));
+        self.touch();
+
+        let _ = self.update_list_of_players().await;
+        let _ = self.update_game_state().await;
+        Ok(bot_id)
+    }
+
+    pub async fn remove_bot(&mut self, host_id: usize, bot_id: usize) -> Result<(), &'static str> {
+        if !self.is_host(host_id) {
+            return Err("Only the host can remove bots");
+        }
+        let is_bot = self.game_player_pool.get_player_by_id(bot_id).is_some_and(|player| player.is_bot);
+        if !is_bot {
+            return Err("Player is not a bot");
+        }
+        self.remove_player(bot_id).await?;
+        let _ = self.update_game_state().await;
+        Ok(())
+    }
+
+    pub fn is_bot_turn(&self) -> bool {
+        self.round_in_progress &&
+            !self.is_paused &&
+            self.rematch_vote.is_none() &&
+            self.bots.contains_key(&self.player_to_play)
+    }
+
+    //what the given player can see of the table
+    pub fn player_view(&self, player_id: usize) -> Option<PlayerView> {
+        let players = self.get_all_players_in_game();
+        let seat = players.iter().position(|player| player.id == player_id)?;
+        let top_card = self.discard_pile.last()?.clone();
+
+        let mut misses = self.misses_this_round();
+        let seat_count = players.len() as isize;
+        let opponents = (1..seat_count)
+            .map(|offset| {
+                let index = (seat as isize) + offset * (self.direction as isize);
+                &players[index.rem_euclid(seat_count) as usize]
+            })
+            .map(|player| OpponentView {
+                player_id: player.id,
+                card_count: player.hand.len(),
+                misses: misses.remove(&player.id).unwrap_or_default(),
+            })
+            .collect();
+
+        Some(PlayerView {
+            player_id,
+            hand: players[seat].hand.clone(),
+            active_color: top_card.color.clone(),
+            discard_pile: self.discard_pile.clone(),
+            top_card,
+            opponents,
+            direction: self.direction,
+            deck_size: self.deck.cards.len(),
+            rules: self.settings.rules.clone(),
+        })
+    }
+
+    //the top cards each player drew on instead of playing this round, read from the event log. A
+    //turn's first draw by the player to play is the choice; later draws add to what they may hold
+    fn misses_this_round(&self) -> HashMap<usize, Vec<Miss>> {
+        let mut misses: HashMap<usize, Vec<Miss>> = HashMap::new();
+        let mut top_card = None;
+        let mut player_to_play = None;
+        let mut drawing = false; // the player to play has drawn this turn
+        for logged in self.events.current_round() {
+            match &logged.event {
+                GameEvent::Dealt { discard_pile, player_to_play: first, .. } => {
+                    top_card = discard_pile.last().cloned();
+                    player_to_play = Some(*first);
+                }
+                GameEvent::CardsPlayed { cards, .. } => {
+                    top_card = cards.last().cloned().or(top_card);
+                    drawing = false;
+                }
+                GameEvent::TurnPassed { player_id } => {
+                    player_to_play = Some(*player_id);
+                    drawing = false;
+                }
+                GameEvent::CardDrawn { player_id, .. } => {
+                    let player_misses = misses.entry(*player_id).or_default();
+                    if Some(*player_id) == player_to_play && !drawing {
+                        drawing = true;
+                        if let Some(top_card) = &top_card {
+                            player_misses.push(Miss { top_card: top_card.clone(), drawn_since: 0 });
+                        }
+                    }
+                    for miss in player_misses {
+                        miss.drawn_since += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        misses
+    }
+
+    //the move the bot whose turn it is has to make, if it is a bot's turn
+    pub fn bot_turn(&self) -> Option<BotTurn> {
+        if !self.is_bot_turn() {
+            return None;
+        }
+        let bot_id = self.player_to_play;
+        Some(BotTurn {
+            game_id: self.id,
+            bot_id,
+            turn: self.events.next_seq(),
+            view: self.player_view(bot_id)?,
+            strategy: self.bots.get(&bot_id)?.clone(),
+        })
+    }
+
+    //make a move worked out for bot_turn, unless the table has moved on since. Returns whether it was made
+    pub async fn apply_bot_turn(&mut self, turn: &BotTurn, action: BotAction) -> bool {
+        if !self.is_bot_turn() || self.player_to_play != turn.bot_id || self.events.next_seq() != turn.turn {
+            return false;
+        }
+        self.perform_bot_action(turn.bot_id, action).await;
+        true
+    }
+
+    //let the bot whose turn it is make its move right away. Returns whether a bot acted
+    pub async fn play_bot_turn(&mut self) -> bool {
+        let Some(turn) = self.bot_turn() else {
+            return false;
+        };
+        let action = turn.choose_action();
+        self.apply_bot_turn(&turn, action).await
+    }
+
+    async fn perform_bot_action(&mut self, player_id: usize, action: BotAction) {
+        let result = match action {
+            BotAction::Play(cards) => self.play_cards(player_id, cards).await,
+            BotAction::Draw => self.draw_for_turn(player_id).await,
+        };
+        if let Err(err) = result {
+            game_log!("Bot {} made an invalid move ({}), drawing instead", player_id, err);
+            let _ = self.draw_for_turn(player_id).await;
+        }
+        let _ = self.update_game_state().await;
+    }
+
+    //whether the player whose turn it is has let their time run out
+    pub fn is_turn_timed_out(&self, now: Instant) -> bool {
+        self.settings.bot_takeover &&
+            self.round_in_progress &&
+            !self.is_paused &&
+            self.rematch_vote.is_none() &&
+            !self.bots.contains_key(&self.player_to_play) &&
+            now.duration_since(self.turn_started_at) >= AFK_TURN_TIMEOUT
+    }
+
+    //a bot makes the move of a player who ran out of time. Once they have done so AFK_TIMEOUT_LIMIT
+    //times in a row, it keeps their seat until they take it back
+    pub async fn handle_turn_timeout(&mut self) {
+        let player_id = self.player_to_play;
+        let missed_turns = self.missed_turns.get(&player_id).copied().unwrap_or_default() + 1;
+        if missed_turns >= AFK_TIMEOUT_LIMIT {
+            let _ = self.take_over_seat(player_id).await;
+            return;
+        }
+
+        let Some(view) = self.player_view(player_id) else {
+            return;
+        };
+        let action = HeuristicStrategy.choose_action(&view);
+        self.perform_bot_action(player_id, action).await;
+        self.missed_turns.insert(player_id, missed_turns);
+    }
+
+    //a bot plays the seat, with the player's current hand, until they resume control
+    pub async fn take_over_seat(&mut self, player_id: usize) -> Result<(), &'static str> {
+        if !self.round_in_progress {
+            return Err("No round in progress");
+        }
+        let player = self.get_player_by_id_mut(player_id).ok_or("Player not found")?;
+        if player.is_bot || player.is_spectator {
+            return Err("Only seated players can be taken over");
+        }
+        player.is_bot_controlled = true;
+        let strategy_seed = self.rng.gen();
+        self.bots.insert(player_id, BotDifficulty::Normal.create_strategy(strategy_seed, &self.settings));
+        self.missed_turns.remove(&player_id);
+        if self.is_host(player_id) {
+            self.migrate_host().await;
+        }
+
+        let message = create_websocket_message(
+            "seat_taken_over",
+            &json!({ "player_id": player_id }).to_string()
+        );
+        self.game_player_pool.broadcast_message(message).await;
+        let _ = self.update_list_of_players().await;
+        let _ = self.update_game_state().await;
+        Ok(())
+    }
+
+    //hand a bot-controlled seat back to its player. A reconnecting player passes their new connection
+    pub async fn resume_control(
+        &mut self,
+        player_id: usize,
+        sender: Option<Sender<String>>
+    ) -> Result<(), &'static str> {
+        let player = self.get_player_by_id_mut(player_id).ok_or("Player not found")?;
+        if !player.is_bot_controlled {
+            return Err("Your seat is not being played by a bot");
+        }
+        player.is_bot_controlled = false;
+        self.bots.remove(&player_id);
+        self.missed_turns.remove(&player_id);
+        if let Some(sender) = sender {
+            self.game_player_pool.reconnect(player_id, sender);
+        }
+        self.empty_since = None;
+        if self.host_id.is_none() {
+            self.host_id = Some(player_id);
+            self.broadcast_host_changed().await;
+        }
+
+        let message = create_websocket_message(
+            "seat_resumed",
+            &json!({ "player_id": player_id }).to_string()
+        );
+        self.game_player_pool.broadcast_message(message).await;
+        let player = self.game_player_pool.get_player_by_id(player_id).unwrap();
+        self.send_chat_history(&player).await;
+        let _ = self.update_list_of_players().await;
+        if self.round_in_progress && self.player_to_play == player_id {
+            self.give_turn_to(player).await;
+        } else {
+            let _ = self.update_single_player(&player).await;
+            let _ = self.update_game_state().await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ card::{ Card, Color, Value }, game_settings::GameSettings, playerpool::PlayerPool };
+    use tokio::sync::mpsc;
+
+    fn game_with_host() -> GameState {
+        let mut game = GameState::new(1, PlayerPool::new(), 1, GameSettings::default());
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.game_player_pool.register_connection(tx, Player::new(1));
+        game
+    }
+
+    #[tokio::test]
+    async fn test_only_host_adds_bots_between_rounds() {
+        let mut game = game_with_host();
+        assert!(game.add_bot(2, BotDifficulty::Easy).await.is_err());
+
+        let bot_id = game.add_bot(1, BotDifficulty::Normal).await.unwrap();
+        let bot = game.game_player_pool.get_player_by_id(bot_id).unwrap();
+        assert!(bot.is_bot);
+        assert!(bot.is_ready);
+        assert_eq!(bot.name, "Normal Bot 1");
+
+        game.get_player_by_id_mut(1).unwrap().name = "Easy Bot 2".to_string();
+        let bot_id = game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+        assert_eq!(game.game_player_pool.get_player_by_id(bot_id).unwrap().name, "Easy Bot 2 2");
+
+        game.round_in_progress = true;
+        assert!(game.add_bot(1, BotDifficulty::Easy).await.is_err());
+    }
+
+    async fn game_in_progress() -> GameState {
+        let mut game = game_with_host();
+        game.reseed(7);
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.game_player_pool.register_connection(tx, Player::new(2));
+        game.start_round().await;
+        game
+    }
+
+    #[tokio::test]
+    async fn test_bot_keeps_seat_until_player_resumes() {
+        let mut game = game_in_progress().await;
+        //a hand whose best move is a plain number card, so the turn always passes to player 2
+        let hand = vec![
+            Card { id: 4, color: Color::Red, value: Value::Number(3) },
+            Card { id: 47, color: Color::Blue, value: Value::Number(7) },
+        ];
+        game.get_player_by_id_mut(1).unwrap().hand = hand.clone();
+        game.discard_pile = vec![Card { id: 6, color: Color::Red, value: Value::Number(5) }];
+        game.player_to_play = 1;
+
+        game.game_player_pool.disconnect(1);
+        game.take_over_seat(1).await.unwrap();
+        let player = game.game_player_pool.get_player_by_id(1).unwrap();
+        assert!(player.is_bot_controlled);
+        assert!(player.to_serializable().is_bot_controlled);
+        assert_eq!(player.hand, hand);
+        assert_eq!(game.host_id, Some(2));
+        assert!(game.is_bot_turn());
+
+        assert!(game.play_bot_turn().await);
+        assert_eq!(game.discard_pile.last(), Some(&hand[0]));
+        assert_eq!(game.game_player_pool.get_player_by_id(1).unwrap().hand, vec![hand[1].clone()]);
+        assert_eq!(game.player_to_play, 2);
+
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.resume_control(1, Some(tx)).await.unwrap();
+        assert!(!game.game_player_pool.get_player_by_id(1).unwrap().is_bot_controlled);
+        assert!(!game.bots.contains_key(&1));
+        assert!(game.resume_control(1, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_timeouts_hand_seat_to_bot() {
+        let mut game = game_in_progress().await;
+        let player_id = game.player_to_play;
+
+        game.turn_started_at = Instant::now() - AFK_TURN_TIMEOUT;
+        assert!(game.is_turn_timed_out(Instant::now()));
+        game.handle_turn_timeout().await;
+        assert!(!game.is_turn_timed_out(Instant::now()));
+        assert_eq!(game.missed_turns.get(&player_id), Some(&1));
+        assert!(!game.bots.contains_key(&player_id));
+
+        game.player_to_play = player_id;
+        game.handle_turn_timeout().await;
+        assert!(game.bots.contains_key(&player_id));
+        assert!(!game.is_turn_timed_out(Instant::now() + AFK_TURN_TIMEOUT));
+    }
+
+    #[tokio::test]
+    async fn test_view_shows_what_opponents_drew_on() {
+        let mut game = game_in_progress().await;
+        let first = game.player_to_play;
+        let second = if first == 1 { 2 } else { 1 };
+        let top_card = game.discard_pile.last().unwrap().clone();
+
+        game.draw_for_turn(first).await.unwrap();
+        let view = game.player_view(second).unwrap();
+        assert_eq!(view.opponents[0].misses, vec![Miss { top_card, drawn_since: 1 }]);
+        //a penalty draw is no choice, but the cards may be playable
+        game.draw_cards(first, 2, false).await.unwrap();
+        let misses = &game.player_view(second).unwrap().opponents[0].misses;
+        assert_eq!(misses.len(), 1);
+        assert_eq!(misses[0].drawn_since, 3);
+
+        game.draw_for_turn(second).await.unwrap();
+        assert_eq!(game.player_view(first).unwrap().opponents[0].misses.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bot_move_is_dropped_once_the_table_moved_on() {
+        let mut game = game_with_host();
+        game.reseed(3);
+        game.add_bot(1, BotDifficulty::Normal).await.unwrap();
+        game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+        game.remove_player(1).await.unwrap();
+        game.start_round().await;
+
+        let turn = game.bot_turn().unwrap();
+        let action = turn.choose_action();
+        assert!(game.play_bot_turn().await);
+        let events = game.events.len();
+        assert!(!game.apply_bot_turn(&turn, action).await);
+        assert_eq!(game.events.len(), events);
+    }
+
+    #[tokio::test]
+    async fn test_bots_play_a_round_to_the_end() {
+        let mut game = game_with_host();
+        let first = game.add_bot(1, BotDifficulty::Normal).await.unwrap();
+        game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+        game.remove_player(1).await.unwrap();
+        game.start_round().await;
+        game.player_to_play = first;
+
+        let mut moves = 0;
+        while game.round_in_progress && moves < 10_000 {
+            assert!(game.play_bot_turn().await);
+            moves += 1;
+        }
+        assert!(!game.round_in_progress);
+    }
+}
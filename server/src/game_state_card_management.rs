@@ -1,6 +1,25 @@
+use serde::Serialize;
 use serde_json::json;
 
-use crate::{ game_state::GameState, card::{ Card, Value }, websocket::create_websocket_message };
+use crate::{
+    game_state::GameState,
+    game_state_events::GameEvent,
+    card::{ Card, Value },
+    websocket::create_websocket_message,
+};
+
+// A move the player whose turn it is could make right now
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LegalMove {
+    //the card goes onto the discard pile first; any selection of its companions may follow in any order
+    Play {
+        card: Card,
+        choose_color: bool, // wilds need a color picked before they are played
+        companions: Vec<Card>, // the other cards of its value in hand, with the multi card rule
+    },
+    Draw,
+}
 
 impl GameState {
     pub async fn draw_cards(
@@ -9,7 +28,7 @@ impl GameState {
         count: usize,
         advance_turn: bool
     ) -> Result<(), &'static str> {
-        println!("Player {} drawing {} cards", player_id, count);
+        game_log!("Player {} drawing {} cards", player_id, count);
 
         let mut cards_to_draw = Vec::new();
 
@@ -19,7 +38,8 @@ impl GameState {
                 self.shuffle_discard_into_deck();
             }
             if let Some(card) = self.deck.draw() {
-                println!("Drew card: {:?} for player_id {}", card, player_id);
+                game_log!("Drew card: {} for player_id {}", card, player_id);
+                self.events.record(GameEvent::CardDrawn { player_id, card: card.clone() });
                 cards_to_draw.push(card);
             }
         }
@@ -29,7 +49,7 @@ impl GameState {
             player.hand.extend(cards_to_draw);
         } else {
             // Handle the case where the player is not found, if needed
-            println!("Player not found");
+            game_log!("Player not found");
         }
 
         if advance_turn {
@@ -39,17 +59,58 @@ impl GameState {
         Ok(())
     }
 
+    //the draw_card action: the player whose turn it is draws one card and play moves on. With the
+    //draw_until_playable rule they keep drawing until they can play, and keep the turn to play it
+    pub async fn draw_for_turn(&mut self, player_id: usize) -> Result<(), &'static str> {
+        if self.is_paused {
+            return Err("Game is paused");
+        }
+        if self.player_to_play != player_id {
+            game_log!("Not your turn");
+            return Err("Not your turn");
+        }
+        if !self.settings.rules.draw_until_playable {
+            return self.draw_cards(player_id, 1, true).await;
+        }
+
+        loop {
+            if self.deck.is_empty() {
+                self.shuffle_discard_into_deck();
+            }
+            let Some(card) = self.deck.draw() else {
+                //nothing left to draw - give up the turn
+                self.next_turn().await;
+                return Ok(());
+            };
+            let playable = self.is_valid_play(&card);
+            self.events.record(GameEvent::CardDrawn { player_id, card: card.clone() });
+            if let Some(player) = self.get_player_by_id_mut(player_id) {
+                player.hand.push(card);
+            }
+            if playable {
+                //hand the turn straight back so the drawn card can be played
+                let player = self.game_player_pool.get_player_by_id(player_id).unwrap();
+                self.give_turn_to(player).await;
+                return Ok(());
+            }
+        }
+    }
+
     pub async fn play_cards(
         &mut self,
         player_id: usize,
         cards: Vec<Card>
     ) -> Result<(), &'static str> {
         let first_card = cards.first().ok_or("No cards provided")?;
-        self.validate_card_play(player_id, &first_card)?;
+        self.validate_card_play(player_id, first_card)?;
+
+        if cards.len() > 1 && !self.settings.rules.multi_card_play {
+            return Err("Only one card may be played per turn");
+        }
 
         //having made sure the first card is valid, we can now check that all cards in the vector are the same value as the first card
         if !cards.iter().all(|card| card.value == first_card.value) {
-            println!("Invalid cards played - not all cards are the same value");
+            game_log!("Invalid cards played - not all cards are the same value");
             return Err("Invalid cards");
         }
 
@@ -75,14 +136,14 @@ impl GameState {
                                 value: card.value.clone(),
                             });
                         } else {
-                            println!("Card played: {:?}", card);
+                            game_log!("Card played: {}", card);
                             played_cards.push(card.clone());
                         }
                         //ultimately, we need to remove the card from the player's hand
                         player_conn.player.hand.remove(pos);
-                        println!("Player {} played card: {:?}", player_id, card);
+                        game_log!("Player {} played card: {}", player_id, card);
                     } else {
-                        println!("Card not in hand");
+                        game_log!("Card not in hand");
                         return Err("Card not in hand");
                     }
                 }
@@ -93,7 +154,10 @@ impl GameState {
 
         //if the player has no cards left, they win the round
         if self.game_player_pool.get_player_by_id(player_id).unwrap().hand.is_empty() {
-            self.end_round();
+            game_log!("Winner found");
+            self.record_play(player_id, &played_cards);
+            self.discard_pile.extend(played_cards);
+            self.finish_round(player_id).await;
             return Ok(());
         }
 
@@ -101,15 +165,21 @@ impl GameState {
         for card in &played_cards {
             if card.value == Value::DrawTwo {
                 let next_player_id = self.get_next_player_id();
-                println!("passing 2 cards to next player id: {} ", &next_player_id.to_string());
+                game_log!("passing 2 cards to next player id: {} ", &next_player_id.to_string());
                 let _ = self.draw_cards(next_player_id, 2, false).await;
+                if self.settings.rules.draw_cards_skip_turn {
+                    self.player_to_play = next_player_id;
+                }
             }
 
             //if there's a wild draw four card in the played cards, we need to draw four cards for the next player
             if card.value == Value::WildDrawFour {
                 let next_player_id = self.get_next_player_id();
-                println!("passing 4 cards to next player id: {} ", &next_player_id.to_string());
+                game_log!("passing 4 cards to next player id: {} ", &next_player_id.to_string());
                 let _ = self.draw_cards(next_player_id, 4, false).await;
+                if self.settings.rules.draw_cards_skip_turn {
+                    self.player_to_play = next_player_id;
+                }
             }
 
             //if there's a skip card in the played cards, we need to skip the next player. This will skip the turn once more in the next_turn function
@@ -122,6 +192,7 @@ impl GameState {
             //if there's a reverse card in the played cards, we need to reverse the direction of play
             if card.value == Value::Reverse {
                 self.direction *= -1;
+                self.events.record(GameEvent::DirectionChanged { direction: self.direction });
             }
         }
 
@@ -140,34 +211,62 @@ impl GameState {
             message
         ).await;
 
+        self.record_play(player_id, &played_cards);
         self.discard_pile.extend(played_cards);
 
-        if let Some(_winner_id) = self.check_winner() {
-            println!("Winner found");
-
-            //create and broadcast a message to all players the id of the winning player
-            let winner_json =
-                json!({
-                    "winner_id": player_id,
-                }).to_string();
-            let message = create_websocket_message("winner_found", &winner_json);
-            self.game_player_pool.broadcast_message(message).await;
-            self.end_round();
-            return Ok(());
-        }
-
         self.next_turn().await;
         Ok(())
     }
 
+    //everything the player could do on their turn: each playable card once, with the cards it
+    //could be played together with, or drawing
+    pub fn legal_moves(&self, player_id: usize) -> Vec<LegalMove> {
+        let your_turn =
+            self.round_in_progress &&
+            !self.is_paused &&
+            self.rematch_vote.is_none() &&
+            self.player_to_play == player_id;
+        let Some(player) = self.game_player_pool.get_player_by_id(player_id) else {
+            return Vec::new();
+        };
+        if !your_turn {
+            return Vec::new();
+        }
+
+        let mut moves = Vec::new();
+        let mut leads_seen = Vec::new(); // identical cards in hand share an id
+        for (lead_index, lead) in player.hand.iter().enumerate() {
+            if !self.is_valid_play(lead) || leads_seen.contains(&lead.id) {
+                continue;
+            }
+            leads_seen.push(lead.id);
+            let companions = if self.settings.rules.multi_card_play {
+                player.hand
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, card)| *index != lead_index && card.value == lead.value)
+                    .map(|(_, card)| card.clone())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            moves.push(LegalMove::Play { card: lead.clone(), choose_color: lead.is_wild(), companions });
+        }
+        moves.push(LegalMove::Draw);
+        moves
+    }
+
     // Simplified card validation in play_card
     fn validate_card_play(&self, player_id: usize, card: &Card) -> Result<(), &'static str> {
+        if self.is_paused {
+            return Err("Game is paused");
+        }
         if self.player_to_play != player_id {
-            println!("Not your turn");
+            game_log!("Not your turn");
             return Err("Not your turn");
         }
-        if !self.is_valid_play(&card) {
-            println!("Invalid play for card: {:?}", card);
+        if !self.is_valid_play(card) {
+            game_log!("Invalid play for card: {}", card);
             return Err("Invalid play");
         }
         Ok(())
@@ -175,13 +274,14 @@ impl GameState {
 
     pub fn shuffle_discard_into_deck(&mut self) {
         let top_card = self.discard_pile.pop().unwrap();
-        self.deck.cards.extend(self.discard_pile.drain(..));
-        self.deck.shuffle();
+        self.deck.cards.append(&mut self.discard_pile);
+        self.deck.shuffle(&mut self.shuffle_rng);
         //we need to make sure that every Wild card that has been played (with a color chosen) is reset back to Color: Wild.
         //This is because the color chosen is stored in the card itself, and we don't want to carry that over to the next round
         //find all cards with a value of Wild or WildDrawFour and set their color to Wild
         self.reset_played_wild_cards();
         self.discard_pile.push(top_card);
+        self.events.record(GameEvent::DiscardPileReshuffled { deck: self.deck.cards.clone() });
     }
 
     pub fn reset_played_wild_cards(&mut self) {
@@ -192,3 +292,85 @@ impl GameState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        card::Color,
+        game_settings::GameSettings,
+        player::Player,
+        playerpool::PlayerPool,
+    };
+    use tokio::sync::mpsc;
+    use crate::test_fixtures::card;
+
+    // Player 1 is to play on a red 5 holding the given hand
+    fn game_with_hand(hand: Vec<Card>, settings: GameSettings) -> GameState {
+        let mut game = GameState::new(1, PlayerPool::new(), 1, settings);
+        for id in 1..=2 {
+            let (tx, _) = mpsc::channel::<String>(1);
+            game.game_player_pool.register_connection(tx, Player::new(id));
+        }
+        game.get_player_by_id_mut(1).unwrap().hand = hand;
+        game.discard_pile = vec![card(6, Color::Red, Value::Number(5))];
+        game.round_in_progress = true;
+        game.player_to_play = 1;
+        game
+    }
+
+    //each playable card's id with the ids of the cards it could be played together with
+    fn played_ids(moves: &[LegalMove]) -> Vec<(usize, Vec<usize>)> {
+        moves
+            .iter()
+            .filter_map(|legal_move| match legal_move {
+                LegalMove::Play { card, companions, .. } =>
+                    Some((card.id, companions.iter().map(|card| card.id).collect())),
+                LegalMove::Draw => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_legal_moves_include_combinations() {
+        let hand = vec![
+            card(19, Color::Yellow, Value::Number(5)),
+            card(3, Color::Red, Value::Number(2)),
+            card(32, Color::Green, Value::Number(5)),
+            card(40, Color::Blue, Value::Number(0)),
+            card(53, Color::Wild, Value::Wild),
+            card(32, Color::Green, Value::Number(5)),
+        ];
+        let game = game_with_hand(hand, GameSettings::default());
+        let moves = game.legal_moves(1);
+
+        //the second green five is listed once, as a companion
+        assert_eq!(played_ids(&moves), vec![
+            (19, vec![32, 32]),
+            (3, vec![]),
+            (32, vec![19, 32]),
+            (53, vec![]),
+        ]);
+        assert!(
+            moves.contains(
+                &(LegalMove::Play { card: card(53, Color::Wild, Value::Wild), choose_color: true, companions: vec![] })
+            )
+        );
+        assert_eq!(moves.last(), Some(&LegalMove::Draw));
+        assert!(game.legal_moves(2).is_empty());
+    }
+
+    #[test]
+    fn test_legal_moves_without_multi_card_play() {
+        let hand = vec![
+            card(19, Color::Yellow, Value::Number(5)),
+            card(32, Color::Green, Value::Number(5)),
+            card(32, Color::Green, Value::Number(5)),
+        ];
+        let mut settings = GameSettings::default();
+        settings.rules.multi_card_play = false;
+        let game = game_with_hand(hand, settings);
+
+        assert_eq!(played_ids(&game.legal_moves(1)), vec![(19, vec![]), (32, vec![])]);
+    }
+}
@@ -0,0 +1,42 @@
+use serde_json::json;
+
+use crate::{
+    chat::{ sanitize_message, ChatMessage },
+    game_state::GameState,
+    player::Player,
+    websocket::create_websocket_message,
+};
+
+// chat between the players and spectators at a table
+impl GameState {
+    pub async fn send_chat(&mut self, player_id: usize, text: &str) -> Result<(), &'static str> {
+        let player = self.game_player_pool.get_player_by_id(player_id).ok_or("Player not in game")?;
+        let text = sanitize_message(text)?;
+
+        let chat_message = ChatMessage::new(player_id, player.name, text);
+        self.chat_history.push(chat_message.clone());
+        self.touch();
+
+        let chat_json =
+            json!({
+                "scope": "game",
+                "game_id": self.id,
+                "message": chat_message,
+            }).to_string();
+        let message = create_websocket_message("chat_message", &chat_json);
+        self.game_player_pool.broadcast_message(message).await;
+        Ok(())
+    }
+
+    //catch a newly joined player up on the conversation
+    pub async fn send_chat_history(&self, player: &Player) {
+        let history_json =
+            json!({
+                "scope": "game",
+                "game_id": self.id,
+                "messages": self.chat_history.messages,
+            }).to_string();
+        let message = create_websocket_message("chat_history", &history_json);
+        self.game_player_pool.send_message(player, message).await;
+    }
+}
@@ -0,0 +1,344 @@
+use serde::{ Deserialize, Serialize };
+
+use crate::{
+    card::{ Card, Color },
+    game_settings::GameSettings,
+    game_state::GameState,
+    player::Player,
+    playerpool::PlayerPool,
+};
+
+// Something that changed the cards or the turn at a table. Each event carries its outcome (the
+// cards drawn, the deck order after a shuffle) rather than the randomness behind it, so replaying
+// the log needs no rng. The log holds every hand and the order of the deck, so it stays on the server
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    Seeded {
+        seed: u64,
+    },
+    Dealt {
+        seats: Vec<DealtSeat>, // in turn order
+        entropy: Vec<String>, // what players mixed into this round's shuffle
+        deck: Vec<Card>,
+        discard_pile: Vec<Card>,
+        player_to_play: usize,
+    },
+    CardDrawn {
+        player_id: usize,
+        card: Card,
+    },
+    CardsPlayed {
+        player_id: usize,
+        cards: Vec<Card>, // wilds carry the chosen color
+    },
+    ColorChosen {
+        player_id: usize,
+        color: Color,
+    },
+    DirectionChanged {
+        direction: i8,
+    },
+    TurnPassed {
+        player_id: usize,
+    },
+    DiscardPileReshuffled {
+        deck: Vec<Card>,
+    },
+    PauseChanged {
+        paused: bool,
+    },
+    PlayerLeft {
+        player_id: usize,
+        deck: Option<Vec<Card>>, // a seated player's hand is shuffled back into the deck
+    },
+    RoundWon {
+        player_id: usize,
+        points: i32,
+    },
+    RoundEnded {
+        deck: Vec<Card>,
+        discard_pile: Vec<Card>,
+    },
+    MatchWon {
+        player_id: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DealtSeat {
+    pub player_id: usize,
+    pub name: String,
+    pub is_bot: bool,
+    pub score: i32,
+    pub hand: Vec<Card>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LoggedEvent {
+    pub seq: u64,
+    pub event: GameEvent,
+}
+
+// Append-only: events can be added and read, never changed or taken out. A log covers one match;
+// the next match starts a new log that carries on the numbering
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EventLog {
+    events: Vec<LoggedEvent>,
+    first_seq: u64, // seq of the first event in this log
+    round_start: Option<usize>, // index of the latest deal
+}
+
+impl EventLog {
+    pub fn record(&mut self, event: GameEvent) -> u64 {
+        let seq = self.next_seq();
+        if matches!(event, GameEvent::Dealt { .. }) {
+            self.round_start = Some(self.events.len());
+        }
+        self.events.push(LoggedEvent { seq, event });
+        seq
+    }
+
+    //hand over this match's events and start an empty log for the next match
+    pub fn start_match(&mut self) -> EventLog {
+        let next = EventLog { events: Vec::new(), first_seq: self.next_seq(), round_start: None };
+        std::mem::replace(self, next)
+    }
+
+    //the log as it stood right after event `seq`
+    pub fn up_to(&self, seq: u64) -> EventLog {
+        let mut log = EventLog { events: Vec::new(), first_seq: self.first_seq, round_start: None };
+        for logged in self.events.iter().take_while(|logged| logged.seq <= seq) {
+            log.record(logged.event.clone());
+        }
+        log
+    }
+
+    pub fn events(&self) -> &[LoggedEvent] {
+        &self.events
+    }
+
+    //the events after `seq`, e.g. for catching up from a known point
+    pub fn since(&self, seq: u64) -> &[LoggedEvent] {
+        let start = seq.saturating_add(1).saturating_sub(self.first_seq);
+        let start = usize::try_from(start).unwrap_or(usize::MAX);
+        self.events.get(start..).unwrap_or_default()
+    }
+
+    //the events from the latest deal on, or nothing before the first one
+    pub fn current_round(&self) -> &[LoggedEvent] {
+        self.round_start.map_or(&[], |start| &self.events[start..])
+    }
+
+    //the seq the next recorded event will get
+    pub fn next_seq(&self) -> u64 {
+        self.first_seq + self.events.len() as u64
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl GameState {
+    //rebuild the table as it was right after event `seq`. Players who never held a seat in a dealt
+    //round, readiness and the bots' strategies aren't logged, so they aren't part of the result
+    pub fn replay(id: usize, settings: GameSettings, log: &EventLog, seq: u64) -> GameState {
+        let mut game = GameState::new(id, PlayerPool::new(), 0, settings);
+        game.host_id = None;
+        let log = log.up_to(seq);
+        for logged in log.events() {
+            game.apply_event(&logged.event);
+        }
+        game.events = log;
+        game
+    }
+
+    pub fn state_at(&self, seq: u64) -> GameState {
+        GameState::replay(self.id, self.settings.clone(), &self.events, seq)
+    }
+
+    fn apply_event(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Seeded { seed } => self.reseed(*seed),
+            GameEvent::Dealt { seats, deck, discard_pile, player_to_play, .. } => {
+                let mut pool = PlayerPool::new();
+                for seat in seats {
+                    let mut player = Player::new(seat.player_id);
+                    player.name = seat.name.clone();
+                    player.is_bot = seat.is_bot;
+                    player.score = seat.score;
+                    player.hand = seat.hand.clone();
+                    player.current_game = Some(self.id);
+                    pool.register_bot(player);
+                }
+                self.game_player_pool = pool;
+                self.deck.cards = deck.clone();
+                self.discard_pile = discard_pile.clone();
+                self.player_to_play = *player_to_play;
+                self.direction = 1;
+                self.round_in_progress = true;
+                self.is_waiting_for_players = false;
+                self.is_paused = false;
+            }
+            GameEvent::CardDrawn { player_id, card } => {
+                self.deck.draw();
+                if let Some(player) = self.get_player_by_id_mut(*player_id) {
+                    player.hand.push(card.clone());
+                }
+            }
+            GameEvent::CardsPlayed { player_id, cards } => {
+                if let Some(player) = self.get_player_by_id_mut(*player_id) {
+                    for card in cards {
+                        if let Some(pos) = player.hand.iter().position(|held| held.id == card.id) {
+                            player.hand.remove(pos);
+                        }
+                    }
+                }
+                self.discard_pile.extend(cards.iter().cloned());
+            }
+            //the color already travels with the played wild
+            GameEvent::ColorChosen { .. } => {}
+            GameEvent::DirectionChanged { direction } => {
+                self.direction = *direction;
+            }
+            GameEvent::TurnPassed { player_id } => {
+                self.player_to_play = *player_id;
+            }
+            GameEvent::DiscardPileReshuffled { deck } => {
+                let top_card = self.discard_pile.pop();
+                self.discard_pile = top_card.into_iter().collect();
+                self.deck.cards = deck.clone();
+            }
+            GameEvent::PauseChanged { paused } => {
+                self.is_paused = *paused;
+            }
+            GameEvent::PlayerLeft { player_id, deck } => {
+                self.game_player_pool.connections.retain(|conn| conn.player.id != *player_id);
+                if let Some(deck) = deck {
+                    self.deck.cards = deck.clone();
+                }
+            }
+            GameEvent::RoundWon { player_id, points } => {
+                if let Some(player) = self.get_player_by_id_mut(*player_id) {
+                    player.score += points;
+                }
+            }
+            GameEvent::RoundEnded { deck, discard_pile } => {
+                for conn in &mut self.game_player_pool.connections {
+                    conn.player.hand.clear();
+                }
+                self.deck.cards = deck.clone();
+                self.discard_pile = discard_pile.clone();
+                self.round_in_progress = false;
+                self.is_paused = false;
+                if let Some(first_player) = self.get_all_players_in_game().first() {
+                    self.player_to_play = first_player.id;
+                }
+            }
+            GameEvent::MatchWon { .. } => {
+                for conn in &mut self.game_player_pool.connections {
+                    conn.player.score = 0;
+                }
+            }
+        }
+    }
+
+    //a play is logged once the cards are on the discard pile, after any draws it forced
+    pub fn record_play(&mut self, player_id: usize, cards: &[Card]) {
+        self.events.record(GameEvent::CardsPlayed { player_id, cards: cards.to_vec() });
+        if let Some(wild) = cards.iter().find(|card| card.is_wild()) {
+            self.events.record(GameEvent::ColorChosen { player_id, color: wild.color.clone() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::BotDifficulty;
+    use tokio::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    struct Snapshot {
+        deck: Vec<Card>,
+        discard_pile: Vec<Card>,
+        hands: Vec<(usize, Vec<Card>, i32)>,
+        player_to_play: usize,
+        direction: i8,
+        round_in_progress: bool,
+    }
+
+    fn snapshot(game: &GameState) -> Snapshot {
+        Snapshot {
+            deck: game.deck.cards.clone(),
+            discard_pile: game.discard_pile.clone(),
+            hands: game
+                .get_all_players_in_game()
+                .into_iter()
+                .map(|player| (player.id, player.hand, player.score))
+                .collect(),
+            player_to_play: game.player_to_play,
+            direction: game.direction,
+            round_in_progress: game.round_in_progress,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_matches_every_turn_of_a_round() {
+        let mut settings = GameSettings::default();
+        settings.rules.multi_card_play = true;
+        let mut game = GameState::new(1, PlayerPool::new(), 1, settings);
+        game.reseed(4);
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.game_player_pool.register_connection(tx, Player::new(1));
+        for difficulty in [BotDifficulty::Normal, BotDifficulty::Easy, BotDifficulty::Easy] {
+            game.add_bot(1, difficulty).await.unwrap();
+        }
+        game.remove_player(1).await.unwrap();
+        game.start_round().await;
+
+        let mut snapshots = vec![(game.events.next_seq() - 1, snapshot(&game))];
+        while game.round_in_progress && snapshots.len() < 10_000 {
+            assert!(game.play_bot_turn().await);
+            snapshots.push((game.events.next_seq() - 1, snapshot(&game)));
+        }
+        assert!(!game.round_in_progress);
+
+        for (seq, expected) in &snapshots {
+            let replayed = game.state_at(*seq);
+            assert_eq!(&snapshot(&replayed), expected, "replay diverged at event {}", seq);
+            assert_eq!(replayed.events.next_seq(), seq + 1);
+        }
+    }
+
+    #[test]
+    fn test_log_is_numbered_in_order() {
+        let mut log = EventLog::default();
+        assert_eq!(log.record(GameEvent::Seeded { seed: 1 }), 0);
+        assert_eq!(log.record(GameEvent::TurnPassed { player_id: 2 }), 1);
+        assert_eq!(log.record(GameEvent::PauseChanged { paused: true }), 2);
+        assert_eq!(log.since(0).len(), 2);
+        assert_eq!(log.since(1)[0].event, GameEvent::PauseChanged { paused: true });
+        assert!(log.since(2).is_empty());
+    }
+
+    #[test]
+    fn test_next_match_starts_a_new_log() {
+        let mut log = EventLog::default();
+        log.record(GameEvent::Seeded { seed: 1 });
+        log.record(GameEvent::MatchWon { player_id: 2 });
+
+        let finished = log.start_match();
+        assert_eq!(finished.len(), 2);
+        assert!(log.is_empty());
+        //the numbering carries on, so a seq never names two events
+        assert_eq!(log.record(GameEvent::Seeded { seed: 2 }), 2);
+        assert_eq!(log.since(1).len(), 1);
+        assert!(log.current_round().is_empty());
+    }
+}
@@ -1,9 +1,12 @@
 use crate::{
-    game_state::GameState,
+    game_settings::MAX_NAME_LENGTH,
+    game_state::{ GameState, MAX_SPECTATORS, MIN_PLAYERS },
+    game_state_events::GameEvent,
     player::{ Player, SerializablePlayer },
     websocket::create_websocket_message,
 };
 use serde_json::json;
+use std::time::Instant;
 use tokio::sync::mpsc::Sender;
 
 // player_management.rs
@@ -13,15 +16,24 @@ impl GameState {
         tx: Sender<String>,
         mut player: Player
     ) -> Result<(), &'static str> {
-        if self.game_player_pool.connections.len() >= 6 {
+        let waiting_count = self.game_player_pool.connections
+            .iter()
+            .filter(|conn| conn.player.is_waiting_for_seat)
+            .count();
+        if self.get_all_players_in_game().len() + waiting_count >= self.settings.max_players {
             return Err("Game is full");
         }
 
         if self.round_in_progress {
-            //Set the player_pools copy of the player to spectator
+            //Set the player_pools copy of the player to spectator until the next round deals them in
+            if self.get_spectators().len() >= MAX_SPECTATORS {
+                return Err("Game is full");
+            }
             player.is_spectator = true;
+            player.is_waiting_for_seat = true;
         }
 
+        player.name = self.unique_name(&player.name, player.id);
         //clone the player so we have a copy to store in the player pool
         let player_clone = player.clone();
 
@@ -32,33 +44,236 @@ impl GameState {
 
         //add the player to the player pool
         self.game_player_pool.register_connection(tx, player_clone);
+        self.empty_since = None;
+        self.touch();
+        self.send_chat_history(&player).await;
 
-        //if the player is the first player to join, set them as the host
+        //if the player is the first player to join, they are first to play
         if self.game_player_pool.connections.len() == 1 {
             self.player_to_play = player.id;
         }
 
+        //if the host left before anyone else joined, the newcomer takes over
+        if self.host_id.is_none() {
+            self.host_id = Some(player.id);
+        }
+
         let _ = self.update_list_of_players().await;
+        let _ = self.update_game_state().await;
+
+        Ok(())
+    }
+
+    pub async fn add_spectator(
+        &mut self,
+        tx: Sender<String>,
+        mut player: Player
+    ) -> Result<(), &'static str> {
+        if self.get_spectators().len() >= MAX_SPECTATORS {
+            return Err("Spectator slots are full");
+        }
+        if self.game_player_pool.connections.iter().any(|conn| conn.player.id == player.id) {
+            return Err("Player already in game");
+        }
 
-        self.check_and_start_round().await;
+        player.is_spectator = true;
+        player.is_waiting_for_seat = false;
+        player.name = self.unique_name(&player.name, player.id);
+        self.game_player_pool.register_connection(tx, player.clone());
+        self.empty_since = None;
+        self.touch();
+        self.send_chat_history(&player).await;
+
+        let _ = self.update_list_of_players().await;
+        let _ = self.update_game_state().await;
 
         Ok(())
     }
 
+    //seat spectators who joined as players mid-round, in the order they arrived, while seats are free
+    pub fn promote_waiting_spectators(&mut self) {
+        let mut seated = self.get_all_players_in_game().len();
+        for conn in self.game_player_pool.connections.iter_mut() {
+            if seated >= self.settings.max_players {
+                break;
+            }
+            if conn.player.is_waiting_for_seat {
+                conn.player.is_spectator = false;
+                conn.player.is_waiting_for_seat = false;
+                seated += 1;
+            }
+        }
+    }
+
     pub async fn remove_player(&mut self, player_id: usize) -> Result<(), &'static str> {
         if
             let Some(pos) = self.game_player_pool.connections
                 .iter()
                 .position(|conn| conn.player.id == player_id)
         {
-            self.game_player_pool.connections.remove(pos);
+            let leaving_player = &self.game_player_pool.connections[pos].player;
+            let was_seated = self.round_in_progress && !leaving_player.is_spectator;
+            let seats_left = self.get_all_players_in_game().len() - usize::from(was_seated);
+            let round_continues = was_seated && seats_left >= MIN_PLAYERS;
+
+            //work out who plays next while the leaving player still has a seat to count from
+            let next_player = if round_continues && self.player_to_play == player_id {
+                Some(self.get_next_player())
+            } else {
+                None
+            };
+
+            let conn = self.game_player_pool.connections.remove(pos);
+            self.bots.remove(&player_id);
+            self.missed_turns.remove(&player_id);
+            self.touch();
+            //a table with only bots left counts as empty
+            if !self.has_human_players() {
+                self.empty_since = Some(Instant::now());
+            }
+
+            let player_left_json = json!({
+                "player_id": player_id,
+            }).to_string();
+            let message = create_websocket_message("player_left", &player_left_json);
+            self.game_player_pool.broadcast_message(message).await;
+
+            //mid-round, the leaving player's hand goes back into the deck so no cards go missing
+            if was_seated {
+                self.deck.cards.extend(conn.player.hand);
+                self.deck.shuffle(&mut self.shuffle_rng);
+            }
+            self.events.record(GameEvent::PlayerLeft {
+                player_id,
+                deck: was_seated.then(|| self.deck.cards.clone()),
+            });
+
+            if was_seated && !round_continues {
+                //nobody left to play against - abort the round and go back to the waiting room
+                self.end_round().await;
+                self.is_waiting_for_players = true;
+                let round_aborted_json = json!({
+                    "reason": "Not enough players",
+                }).to_string();
+                let message = create_websocket_message("round_aborted", &round_aborted_json);
+                self.game_player_pool.broadcast_message(message).await;
+            } else if let Some(next_player) = next_player {
+                self.give_turn_to(next_player).await;
+            }
+
+            if self.is_host(player_id) {
+                self.migrate_host().await;
+            }
             let _ = self.update_list_of_players().await;
+            self.start_round_if_all_ready().await;
             Ok(())
         } else {
             Err("Player not found")
         }
     }
 
+    pub async fn kick_player(&mut self, host_id: usize, target_id: usize) -> Result<(), &'static str> {
+        if !self.is_host(host_id) {
+            return Err("Only the host can kick players");
+        }
+        if host_id == target_id {
+            return Err("Host cannot kick themselves");
+        }
+        let target = self.game_player_pool.get_player_by_id(target_id).ok_or("Player not found")?;
+
+        //let the kicked player know before their connection is dropped from the game pool
+        let message = create_websocket_message("kicked_from_game", &self.id.to_string());
+        self.game_player_pool.send_message(&target, message).await;
+
+        self.remove_player(target_id).await
+    }
+
+    pub async fn transfer_host(
+        &mut self,
+        host_id: usize,
+        target_id: usize
+    ) -> Result<(), &'static str> {
+        if !self.is_host(host_id) {
+            return Err("Only the host can transfer host");
+        }
+        if host_id == target_id {
+            return Err("Player is already the host");
+        }
+        let target = self.game_player_pool.get_player_by_id(target_id).ok_or("Player not found")?;
+        //the same players migrate_host passes over; a bot as host would leave nobody to run the table
+        if target.is_bot || target.is_bot_controlled || target.is_spectator {
+            return Err("Only seated players can be host");
+        }
+        self.host_id = Some(target_id);
+        self.broadcast_host_changed().await;
+        Ok(())
+    }
+
+    //hand the host role to the longest-seated remaining player, preferring active players over spectators.
+    //bots are never host, and neither is anyone a bot is standing in for
+    pub async fn migrate_host(&mut self) {
+        self.host_id = self.game_player_pool.connections
+            .iter()
+            .filter(|conn| !conn.player.is_bot && !conn.player.is_bot_controlled)
+            .min_by_key(|conn| conn.player.is_spectator)
+            .map(|conn| conn.player.id);
+        if self.host_id.is_some() {
+            self.broadcast_host_changed().await;
+        }
+    }
+
+    pub async fn broadcast_host_changed(&self) {
+        let host_json = json!({
+            "game_id": self.id,
+            "host_id": self.host_id,
+        }).to_string();
+        let message = create_websocket_message("host_changed", &host_json);
+        self.game_player_pool.broadcast_message(message).await;
+        let _ = self.update_game_state().await;
+    }
+
+    //names are unique within a game, ignoring case
+    pub fn is_name_taken(&self, name: &str, player_id: usize) -> bool {
+        self.game_player_pool.connections
+            .iter()
+            .any(|conn| conn.player.id != player_id && conn.player.name.eq_ignore_ascii_case(name))
+    }
+
+    //the name as it is, or - if someone at the table already has it - with the lowest free number
+    //after it, shortening the name where the number would not fit otherwise
+    pub fn unique_name(&self, name: &str, player_id: usize) -> String {
+        if !self.is_name_taken(name, player_id) {
+            return name.to_string();
+        }
+        (2..)
+            .map(|number| {
+                let suffix = format!(" {}", number);
+                let base: String = name.chars().take(MAX_NAME_LENGTH.saturating_sub(suffix.len())).collect();
+                format!("{}{}", base.trim_end(), suffix)
+            })
+            .find(|candidate| !self.is_name_taken(candidate, player_id))
+            .unwrap()
+    }
+
+    //mirror a profile change made in the lobby onto this game's copy of the player
+    pub async fn update_player_profile(&mut self, updated: &Player) {
+        if let Some(player) = self.get_player_by_id_mut(updated.id) {
+            player.name = updated.name.clone();
+            player.avatar = updated.avatar.clone();
+            player.color = updated.color.clone();
+        }
+        if let Some(player) = self.game_player_pool.get_player_by_id(updated.id) {
+            let _ = self.update_single_player(&player).await;
+        }
+        let _ = self.update_list_of_players().await;
+        let _ = self.update_game_state().await;
+    }
+
+    //bots and disconnected players don't count
+    pub fn has_human_players(&self) -> bool {
+        self.game_player_pool.connections.iter().any(|conn| conn.is_connected())
+    }
+
     pub fn get_player_count(&self) -> usize {
         self.game_player_pool.connections.len()
     }
@@ -78,7 +293,7 @@ impl GameState {
     pub async fn update_single_player(&self, player: &Player) {
         let player_data_json = serialize_player_data(player);
         let message = create_websocket_message("update_player", &player_data_json);
-        self.game_player_pool.send_message(&player, message).await;
+        self.game_player_pool.send_message(player, message).await;
     }
 
     //function to let players receive an update about the game state via the pool connection.
@@ -88,16 +303,34 @@ impl GameState {
         let info_object =
             json!({
             "id": self.id,
+            "name": self.settings.display_name(self.id),
+            "settings": self.settings,
+            "host_id": self.host_id,
+            "invite_code": self.invite_code,
             "round_in_progress": self.round_in_progress,
+            "is_paused": self.is_paused,
+            "pause_votes": self.pause_votes,
             "player_to_play": self.player_to_play,
             "direction": self.direction,
             "discard_pile": self.discard_pile,
             "deck_size": self.deck.cards.len(),
             "player_count": self.game_player_pool.connections.len(),
-            "players": self.game_player_pool.connections
+            "players": self.get_all_players_in_game()
                 .iter()
-                .map(|conn| conn.player.to_serializable())
+                .map(|player| player.to_serializable())
                 .collect::<Vec<SerializablePlayer>>(),
+            "spectators": self.get_spectators()
+                .iter()
+                .map(|player| player.to_serializable())
+                .collect::<Vec<SerializablePlayer>>(),
+            "shuffle": {
+                "commitment": self.fair_shuffle.commitment,
+                "client_entropy": self.fair_shuffle.client_entropy
+                    .iter()
+                    .map(|(player_id, _)| player_id)
+                    .collect::<Vec<_>>(),
+                "last_reveal": self.last_reveal,
+            },
         });
 
         let game_state_data_json = serde_json::to_string(&info_object).unwrap();
@@ -114,6 +347,14 @@ impl GameState {
             .collect()
     }
 
+    pub fn get_spectators(&self) -> Vec<Player> {
+        self.game_player_pool.connections
+            .iter()
+            .filter(|conn| conn.player.is_spectator)
+            .map(|conn| conn.player.clone())
+            .collect()
+    }
+
     pub fn get_player_by_id_mut(&mut self, player_id: usize) -> Option<&mut Player> {
         //get a mutable reference to the player in the player pool
         if
@@ -128,7 +369,7 @@ impl GameState {
     }
 
     pub fn get_next_player(&self) -> Player {
-        println!("Getting next player");
+        game_log!("Getting next player");
 
         //we want to loop through the player pool and get a list of players who are not spectators
 
@@ -138,11 +379,10 @@ impl GameState {
             .map(|conn| &conn.player) // Map to the player field
             .collect::<Vec<&crate::player::Player>>();
 
-        //get the index of the current player
-        let mut player_index = players
-            .iter()
-            .position(|p| p.id == self.player_to_play)
-            .unwrap();
+        //get the index of the current player. If they no longer hold a seat, play restarts from the first seat
+        let Some(mut player_index) = players.iter().position(|p| p.id == self.player_to_play) else {
+            return players[0].clone();
+        };
 
         // Step to the next player in the player list - the game's direction determines whether we increment or decrement the index
         player_index = if self.direction == 1 {
@@ -164,14 +404,184 @@ impl GameState {
 }
 
 // Helper function to serialize player data to JSON
-fn serialize_player_data(player: &Player) -> String {
+pub fn serialize_player_data(player: &Player) -> String {
     let json =
         json!({
         "id": player.id,
         "name": player.name,
         "hand": player.hand,
         "current_game": player.current_game,
-        "is_spectator": player.is_spectator
+        "is_spectator": player.is_spectator,
+        "is_ready": player.is_ready,
+        "avatar": player.avatar,
+        "color": player.color
     });
     serde_json::to_string(&json).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ strategy::BotDifficulty, test_fixtures::game_with_players };
+    use tokio::sync::mpsc;
+
+    fn total_cards(game: &GameState) -> usize {
+        let in_hands: usize = game.game_player_pool.connections
+            .iter()
+            .map(|conn| conn.player.hand.len())
+            .sum();
+        game.deck.cards.len() + game.discard_pile.len() + in_hands
+    }
+
+    #[tokio::test]
+    async fn test_remove_current_player_passes_turn() {
+        let mut game = game_with_players(3);
+        game.start_round().await;
+        assert_eq!(game.player_to_play, 1);
+
+        game.remove_player(1).await.unwrap();
+
+        assert!(game.round_in_progress);
+        assert_eq!(game.player_to_play, 2);
+        assert_eq!(total_cards(&game), 108);
+    }
+
+    #[tokio::test]
+    async fn test_remove_other_player_keeps_turn() {
+        let mut game = game_with_players(3);
+        game.start_round().await;
+
+        game.remove_player(3).await.unwrap();
+
+        assert!(game.round_in_progress);
+        assert_eq!(game.player_to_play, 1);
+        assert_eq!(total_cards(&game), 108);
+    }
+
+    #[tokio::test]
+    async fn test_remove_player_ends_round_below_minimum() {
+        let mut game = game_with_players(2);
+        game.start_round().await;
+
+        game.remove_player(1).await.unwrap();
+
+        assert!(!game.round_in_progress);
+        assert!(game.is_waiting_for_players);
+        assert_eq!(game.player_to_play, 2);
+        assert_eq!(game.get_player_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_remove_host_migrates_host() {
+        let mut game = game_with_players(3);
+
+        game.remove_player(1).await.unwrap();
+
+        assert_eq!(game.host_id, Some(2));
+        game.remove_player(2).await.unwrap();
+        game.remove_player(3).await.unwrap();
+        assert_eq!(game.host_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_spectator_promoted_at_next_round() {
+        let mut game = game_with_players(2);
+        game.start_round().await;
+
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.add_player(tx, Player::new(3)).await.unwrap();
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.add_spectator(tx, Player::new(4)).await.unwrap();
+        assert_eq!(game.get_spectators().len(), 2);
+
+        game.end_round().await;
+        game.start_round().await;
+
+        let spectators = game.get_spectators();
+        assert_eq!(spectators.len(), 1);
+        assert_eq!(spectators[0].id, 4);
+        assert_eq!(game.get_all_players_in_game().len(), 3);
+        assert_eq!(total_cards(&game), 108);
+    }
+
+    #[tokio::test]
+    async fn test_only_the_host_runs_the_table() {
+        let mut game = game_with_players(1);
+        assert_eq!(game.start_game(1).await, Err("Not enough players to start"));
+        for id in 2..=3 {
+            let (tx, _) = mpsc::channel::<String>(1);
+            game.add_player(tx, Player::new(id)).await.unwrap();
+        }
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.add_spectator(tx, Player::new(4)).await.unwrap();
+        let bot_id = game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+
+        assert_eq!(game.start_game(2).await, Err("Only the host can start the game"));
+        assert_eq!(game.kick_player(2, 3).await, Err("Only the host can kick players"));
+        assert_eq!(game.kick_player(1, 1).await, Err("Host cannot kick themselves"));
+        assert_eq!(game.transfer_host(2, 3).await, Err("Only the host can transfer host"));
+        assert_eq!(game.transfer_host(1, 42).await, Err("Player not found"));
+        assert_eq!(game.transfer_host(1, bot_id).await, Err("Only seated players can be host"));
+        assert_eq!(game.transfer_host(1, 4).await, Err("Only seated players can be host"));
+
+        game.transfer_host(1, 2).await.unwrap();
+        assert_eq!(game.host_id, Some(2));
+        game.kick_player(2, 3).await.unwrap();
+        assert!(game.game_player_pool.get_player_by_id(3).is_none());
+        game.start_game(2).await.unwrap();
+        assert!(game.round_in_progress);
+        assert_eq!(game.start_game(2).await, Err("Round already in progress"));
+    }
+
+    #[tokio::test]
+    async fn test_waiting_player_seated_when_the_round_is_aborted() {
+        let mut game = game_with_players(2);
+        game.start_game(1).await.unwrap();
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.add_player(tx, Player::new(3)).await.unwrap();
+
+        game.remove_player(2).await.unwrap();
+
+        assert!(!game.round_in_progress);
+        assert!(game.get_spectators().is_empty());
+        game.set_ready(3, true).await.unwrap();
+        game.start_game(1).await.unwrap();
+        assert!(game.round_in_progress);
+        assert_eq!(game.get_all_players_in_game().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_joining_with_a_taken_name_gets_a_number() {
+        let mut game = game_with_players(1);
+        game.get_player_by_id_mut(1).unwrap().name = "Sam".to_string();
+        for id in 2..=3 {
+            let mut player = Player::new(id);
+            player.name = "sam".to_string();
+            let (tx, _) = mpsc::channel::<String>(1);
+            game.add_player(tx, player).await.unwrap();
+        }
+        let mut spectator = Player::new(4);
+        spectator.name = "Sam".to_string();
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.add_spectator(tx, spectator).await.unwrap();
+
+        let names: Vec<String> = game.game_player_pool.connections
+            .iter()
+            .map(|conn| conn.player.name.clone())
+            .collect();
+        assert_eq!(names, vec!["Sam", "sam 2", "sam 3", "Sam 4"]);
+
+        //a numbered name still fits the limit
+        let long_name = "x".repeat(MAX_NAME_LENGTH);
+        game.get_player_by_id_mut(1).unwrap().name = long_name.clone();
+        let numbered = game.unique_name(&long_name, 5);
+        assert_eq!(numbered.chars().count(), MAX_NAME_LENGTH);
+        assert!(numbered.ends_with(" 2"));
+    }
+
+    #[tokio::test]
+    async fn test_remove_unknown_player() {
+        let mut game = game_with_players(2);
+        assert_eq!(game.remove_player(42).await, Err("Player not found"));
+    }
+}
@@ -0,0 +1,118 @@
+use std::{ collections::HashMap, time::{ Duration, Instant } };
+
+use serde_json::json;
+
+use crate::{ game_state::GameState, websocket::create_websocket_message };
+
+pub const REMATCH_VOTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Open from the end of a match until everyone has voted or the timeout passes
+#[derive(Debug)]
+pub struct RematchVote {
+    pub eligible: Vec<usize>, // the players seated when the match ended, in seat order
+    pub votes: HashMap<usize, bool>,
+    pub bots: Vec<usize>, // vote yes on their own
+    pub started_at: Instant,
+}
+
+impl RematchVote {
+    pub fn accepted(&self) -> Vec<usize> {
+        self.eligible
+            .iter()
+            .filter(|player_id| self.votes.get(player_id) == Some(&true))
+            .copied()
+            .collect()
+    }
+
+    //bots are always up for another match, so it takes a person to want one
+    pub fn accepted_by_a_player(&self) -> bool {
+        self.accepted()
+            .iter()
+            .any(|player_id| !self.bots.contains(player_id))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.eligible.iter().all(|player_id| self.votes.contains_key(player_id))
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.started_at) >= REMATCH_VOTE_TIMEOUT
+    }
+}
+
+impl GameState {
+    pub async fn start_rematch_vote(&mut self) {
+        let players = self.get_all_players_in_game();
+        let eligible: Vec<usize> = players
+            .iter()
+            .map(|player| player.id)
+            .collect();
+        let vote_json =
+            json!({
+                "game_id": self.id,
+                "players": eligible,
+                "timeout": REMATCH_VOTE_TIMEOUT.as_secs(),
+            }).to_string();
+        //bots always want another go
+        let bots: Vec<usize> = players
+            .iter()
+            .filter(|player| player.is_bot)
+            .map(|player| player.id)
+            .collect();
+        self.rematch_vote = Some(RematchVote {
+            eligible,
+            votes: bots
+                .iter()
+                .map(|&bot_id| (bot_id, true))
+                .collect(),
+            bots,
+            started_at: Instant::now(),
+        });
+        let message = create_websocket_message("rematch_vote_started", &vote_json);
+        self.game_player_pool.broadcast_message(message).await;
+    }
+
+    //returns whether everyone has now voted
+    pub async fn cast_rematch_vote(&mut self, player_id: usize, accept: bool) -> Result<bool, &'static str> {
+        let vote = self.rematch_vote.as_mut().ok_or("No rematch vote in progress")?;
+        if !vote.eligible.contains(&player_id) {
+            return Err("You did not play in the last match");
+        }
+        vote.votes.insert(player_id, accept);
+        let complete = vote.is_complete();
+
+        let vote_json =
+            json!({
+                "game_id": self.id,
+                "accepted": vote.accepted(),
+                "votes_cast": vote.votes.len(),
+                "players": vote.eligible,
+            }).to_string();
+        let message = create_websocket_message("rematch_vote_update", &vote_json);
+        self.game_player_pool.broadcast_message(message).await;
+        self.touch();
+        Ok(complete)
+    }
+
+    //the players who turned the rematch down have already been sent back to the lobby
+    pub async fn start_rematch(&mut self) {
+        if self.settings.rotate_seats {
+            //the first seat moves to the back so someone else leads off
+            let first_seat = self.game_player_pool.connections
+                .iter()
+                .position(|conn| !conn.player.is_spectator);
+            if let Some(first_seat) = first_seat {
+                let conn = self.game_player_pool.connections.remove(first_seat);
+                self.game_player_pool.connections.push(conn);
+            }
+        }
+        for conn in &mut self.game_player_pool.connections {
+            conn.player.score = 0;
+        }
+
+        let message = create_websocket_message("rematch_started", &self.id.to_string());
+        self.game_player_pool.broadcast_message(message).await;
+        self.is_waiting_for_players = false;
+        self.start_round().await;
+    }
+}
@@ -0,0 +1,479 @@
+use std::time::{ Duration, Instant };
+
+use rand::{ rngs::StdRng, seq::SliceRandom, Rng, SeedableRng };
+
+use crate::{
+    card::{ Card, Color, Value },
+    deck::Deck,
+    game_settings::Ruleset,
+    strategy::{ BotAction, Miss, PlayerView, Strategy },
+};
+
+const EXPLORATION: f64 = 0.7;
+const MAX_PLAYOUT_MOVES: usize = 400;
+const CHOSEN_COLORS: [Color; 4] = [Color::Red, Color::Yellow, Color::Green, Color::Blue];
+
+// Information-set Monte Carlo tree search (single observer). Every iteration deals the cards the
+// bot cannot see at random - consistent with the opponents' hand sizes, the deck size, the discard
+// pile and, where the cards allow it, the top cards opponents drew on - and then plays the round
+// out on that deal, sharing one tree across all deals
+#[derive(Debug)]
+pub struct IsmctsStrategy {
+    time_budget: Duration,
+    max_iterations: usize,
+    rng: StdRng,
+}
+
+impl IsmctsStrategy {
+    pub fn new(time_budget: Duration, seed: u64) -> Self {
+        Self {
+            time_budget,
+            max_iterations: usize::MAX,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    //a fixed number of iterations instead of a time budget, so benchmark runs are reproducible
+    pub fn with_iterations(max_iterations: usize, seed: u64) -> Self {
+        Self {
+            time_budget: Duration::MAX,
+            max_iterations,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn search(&mut self, view: &PlayerView) -> Move {
+        let started_at = Instant::now();
+        let mut tree = vec![Node::root()];
+        let mut iterations = 0;
+
+        while iterations < self.max_iterations && started_at.elapsed() < self.time_budget {
+            let mut round = SimulatedRound::determinize(view, &mut self.rng);
+            let mut path = vec![0];
+            let mut node = 0;
+
+            //selection and expansion
+            while round.winner.is_none() {
+                let legal_moves = round.legal_moves();
+                let untried: Vec<&Move> = legal_moves
+                    .iter()
+                    .filter(|mv| !tree[node].children.iter().any(|&child| &tree[child].mv == *mv))
+                    .collect();
+
+                if let Some(&mv) = untried.choose(&mut self.rng) {
+                    let child = tree.len();
+                    tree.push(Node::new(mv.clone(), round.current));
+                    tree[node].children.push(child);
+                    round.apply(mv, &mut self.rng);
+                    path.push(child);
+                    break;
+                }
+
+                let available: Vec<usize> = tree[node].children
+                    .iter()
+                    .copied()
+                    .filter(|&child| legal_moves.contains(&tree[child].mv))
+                    .collect();
+                for &child in &available {
+                    tree[child].availability += 1.0;
+                }
+                let child = *available
+                    .iter()
+                    .max_by(|&&a, &&b| tree[a].ucb().total_cmp(&tree[b].ucb()))
+                    .unwrap();
+                round.apply(&tree[child].mv, &mut self.rng);
+                path.push(child);
+                node = child;
+            }
+
+            //playout
+            let mut moves = 0;
+            while round.winner.is_none() && moves < MAX_PLAYOUT_MOVES {
+                let mv = round.playout_move(&mut self.rng);
+                round.apply(&mv, &mut self.rng);
+                moves += 1;
+            }
+
+            //backpropagation - each node is scored for the seat that made its move
+            for &index in &path {
+                let node = &mut tree[index];
+                node.visits += 1.0;
+                if round.winner.is_some() && round.winner == node.seat {
+                    node.wins += 1.0;
+                }
+            }
+            iterations += 1;
+        }
+
+        tree[0].children
+            .iter()
+            .max_by(|&&a, &&b| tree[a].visits.total_cmp(&tree[b].visits))
+            .map(|&child| tree[child].mv.clone())
+            .unwrap_or(Move::Draw)
+    }
+}
+
+impl Strategy for IsmctsStrategy {
+    fn choose_action(&mut self, view: &PlayerView) -> BotAction {
+        let legal_moves = SimulatedRound::from_view(view, Vec::new(), Vec::new()).legal_moves();
+        let mv = match legal_moves.as_slice() {
+            [only_move] => only_move.clone(),
+            _ => self.search(view),
+        };
+        match mv {
+            Move::Play(cards) => BotAction::Play(cards),
+            Move::Draw => BotAction::Draw,
+        }
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+//take `count` cards from the shuffled unseen ones, passing over any that would give the opponent
+//more cards playable on a missed top card than they drew since. Should the cards that fit run out,
+//the rest of the hand is made up of whatever is left
+fn deal_hand(unseen: &mut Vec<Card>, count: usize, misses: &[Miss]) -> Vec<Card> {
+    let count = count.min(unseen.len());
+    let mut hand = Vec::with_capacity(count);
+    let mut playable = vec![0; misses.len()]; // per miss, the dealt cards playable on it
+    let mut index = 0;
+    while hand.len() < count && index < unseen.len() {
+        let card = &unseen[index];
+        let fits = misses
+            .iter()
+            .zip(&playable)
+            .all(|(miss, &playable)| playable < miss.drawn_since || !card.can_be_played_on(&miss.top_card));
+        if !fits {
+            index += 1;
+            continue;
+        }
+        for (miss, playable) in misses.iter().zip(&mut playable) {
+            if card.can_be_played_on(&miss.top_card) {
+                *playable += 1;
+            }
+        }
+        hand.push(unseen.swap_remove(index));
+    }
+    let missing = count - hand.len();
+    hand.extend(unseen.drain(unseen.len() - missing..));
+    hand
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Move {
+    Play(Vec<Card>),
+    Draw,
+}
+
+#[derive(Debug)]
+struct Node {
+    mv: Move,
+    seat: Option<usize>, // who made the move leading here, None for the root
+    children: Vec<usize>,
+    visits: f64,
+    wins: f64,
+    availability: f64,
+}
+
+impl Node {
+    fn root() -> Self {
+        Self {
+            mv: Move::Draw,
+            seat: None,
+            children: Vec::new(),
+            visits: 0.0,
+            wins: 0.0,
+            availability: 1.0,
+        }
+    }
+
+    fn new(mv: Move, seat: usize) -> Self {
+        Self { seat: Some(seat), mv, ..Self::root() }
+    }
+
+    fn ucb(&self) -> f64 {
+        self.wins / self.visits + EXPLORATION * (self.availability.ln() / self.visits).sqrt()
+    }
+}
+
+// A round with every hidden card filled in. Seat 0 is the searching player, the other seats
+// follow in the order play was going when the search started
+#[derive(Debug, Clone)]
+struct SimulatedRound {
+    hands: Vec<Vec<Card>>,
+    deck: Vec<Card>,
+    discard_pile: Vec<Card>,
+    current: usize,
+    direction: isize,
+    rules: Ruleset,
+    winner: Option<usize>,
+}
+
+impl SimulatedRound {
+    fn from_view(view: &PlayerView, opponent_hands: Vec<Vec<Card>>, deck: Vec<Card>) -> Self {
+        let mut hands = vec![view.hand.clone()];
+        hands.extend(opponent_hands);
+        hands.resize(view.opponents.len() + 1, Vec::new());
+        Self {
+            hands,
+            deck,
+            discard_pile: view.discard_pile.clone(),
+            current: 0,
+            direction: 1,
+            rules: view.rules.clone(),
+            winner: None,
+        }
+    }
+
+    //deal the cards the player hasn't seen - the full deck minus their hand and the discard pile - at random
+    fn determinize(view: &PlayerView, rng: &mut impl Rng) -> Self {
+        let mut unseen = Deck::new().cards;
+        for seen in view.hand.iter().chain(&view.discard_pile) {
+            if let Some(pos) = unseen.iter().position(|card| card.id == seen.id) {
+                unseen.swap_remove(pos);
+            }
+        }
+        unseen.shuffle(rng);
+
+        let opponent_hands = view.opponents
+            .iter()
+            .map(|opponent| deal_hand(&mut unseen, opponent.card_count, &opponent.misses))
+            .collect();
+        Self::from_view(view, opponent_hands, unseen)
+    }
+
+    fn top_card(&self) -> &Card {
+        self.discard_pile.last().unwrap()
+    }
+
+    fn seat_after(&self, seat: usize) -> usize {
+        let seats = self.hands.len() as isize;
+        ((seat as isize) + self.direction).rem_euclid(seats) as usize
+    }
+
+    //a quick move for the playouts: any playable card rather than drawing, along with the rest of
+    //its value when that is allowed. Wilds take the most held color
+    fn playout_move(&self, rng: &mut impl Rng) -> Move {
+        let hand = &self.hands[self.current];
+        let top_card = self.top_card();
+        let playable: Vec<&Card> = hand
+            .iter()
+            .filter(|card| card.can_be_played_on(top_card))
+            .collect();
+        let Some(&card) = playable.choose(rng) else {
+            return Move::Draw;
+        };
+
+        let mut cards = vec![card.clone()];
+        if self.rules.multi_card_play {
+            let first = hand.iter().position(|held| held.id == card.id).unwrap();
+            cards.extend(
+                hand
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, held)| *index != first && held.value == card.value)
+                    .map(|(_, held)| held.clone())
+            );
+        }
+        if card.is_wild() {
+            let color = CHOSEN_COLORS.into_iter()
+                .max_by_key(|color| hand.iter().filter(|held| &held.color == color).count())
+                .unwrap();
+            for card in &mut cards {
+                card.color = color.clone();
+            }
+        }
+        Move::Play(cards)
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        let hand = &self.hands[self.current];
+        let top_card = self.top_card();
+        let mut moves = vec![Move::Draw];
+
+        let mut seen_ids = Vec::new();
+        for card in hand.iter().filter(|card| card.can_be_played_on(top_card)) {
+            if seen_ids.contains(&card.id) {
+                continue;
+            }
+            seen_ids.push(card.id);
+
+            //optionally play every other card of that value along with it
+            let mut plays = vec![vec![card.clone()]];
+            let mut others = hand.clone();
+            others.remove(others.iter().position(|other| other.id == card.id).unwrap());
+            others.retain(|other| other.value == card.value);
+            if self.rules.multi_card_play && !others.is_empty() {
+                plays.push(std::iter::once(card.clone()).chain(others).collect());
+            }
+
+            for play in plays {
+                if !card.is_wild() {
+                    moves.push(Move::Play(play));
+                    continue;
+                }
+                for color in CHOSEN_COLORS {
+                    let colored = play
+                        .iter()
+                        .map(|card| Card { color: color.clone(), ..card.clone() })
+                        .collect();
+                    moves.push(Move::Play(colored));
+                }
+            }
+        }
+        moves
+    }
+
+    fn draw(&mut self, seat: usize, count: usize, rng: &mut impl Rng) {
+        for _ in 0..count {
+            if self.deck.is_empty() {
+                self.refill_deck(rng);
+            }
+            match self.deck.pop() {
+                Some(card) => self.hands[seat].push(card),
+                None => {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn refill_deck(&mut self, rng: &mut impl Rng) {
+        let top_card = self.discard_pile.pop().unwrap();
+        self.deck.append(&mut self.discard_pile);
+        for card in &mut self.deck {
+            if card.is_wild() {
+                card.color = Color::Wild;
+            }
+        }
+        self.deck.shuffle(rng);
+        self.discard_pile.push(top_card);
+    }
+
+    //the same rules GameState::play_cards and GameState::draw_for_turn apply
+    fn apply(&mut self, mv: &Move, rng: &mut impl Rng) {
+        let seat = self.current;
+        let cards = match mv {
+            Move::Draw => {
+                if !self.rules.draw_until_playable {
+                    self.draw(seat, 1, rng);
+                    self.current = self.seat_after(seat);
+                    return;
+                }
+                loop {
+                    let hand_size = self.hands[seat].len();
+                    self.draw(seat, 1, rng);
+                    if self.hands[seat].len() == hand_size {
+                        self.current = self.seat_after(seat);
+                        return;
+                    }
+                    if self.hands[seat].last().unwrap().can_be_played_on(self.top_card()) {
+                        return;
+                    }
+                }
+            }
+            Move::Play(cards) => cards,
+        };
+
+        for card in cards {
+            let hand = &mut self.hands[seat];
+            let pos = hand.iter().position(|held| held.id == card.id).unwrap();
+            hand.remove(pos);
+        }
+        if self.hands[seat].is_empty() {
+            self.discard_pile.extend(cards.iter().cloned());
+            self.winner = Some(seat);
+            return;
+        }
+
+        for card in cards {
+            match card.value {
+                Value::DrawTwo | Value::WildDrawFour => {
+                    let victim = self.seat_after(self.current);
+                    let count = if card.value == Value::DrawTwo { 2 } else { 4 };
+                    self.draw(victim, count, rng);
+                    if self.rules.draw_cards_skip_turn {
+                        self.current = victim;
+                    }
+                }
+                Value::Skip => {
+                    self.current = self.seat_after(self.current);
+                }
+                Value::Reverse => {
+                    self.direction *= -1;
+                }
+                _ => {}
+            }
+        }
+        self.discard_pile.extend(cards.iter().cloned());
+        self.current = self.seat_after(self.current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{ card, view };
+
+    #[test]
+    fn test_determinize_keeps_card_counts() {
+        let view = view(
+            vec![card(1, Color::Red, Value::Number(0)), card(53, Color::Wild, Value::Wild)],
+            card(2, Color::Red, Value::Number(1)),
+            7
+        );
+        let round = SimulatedRound::determinize(&view, &mut StdRng::seed_from_u64(1));
+        assert_eq!(round.hands[1].len(), 7);
+        let total = round.hands.iter().map(Vec::len).sum::<usize>() + round.deck.len() + round.discard_pile.len();
+        assert_eq!(total, 108);
+    }
+
+    #[test]
+    fn test_determinize_respects_missed_top_cards() {
+        let top_card = card(2, Color::Red, Value::Number(1));
+        let mut view = view(vec![card(1, Color::Red, Value::Number(0))], top_card.clone(), 5);
+        view.opponents[0].misses = vec![Miss { top_card, drawn_since: 1 }];
+
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..50 {
+            let round = SimulatedRound::determinize(&view, &mut rng);
+            let playable = round.hands[1]
+                .iter()
+                .filter(|card| card.can_be_played_on(&view.top_card))
+                .count();
+            assert!(playable <= 1);
+            assert_eq!(round.hands[1].len(), 5);
+        }
+    }
+
+    #[test]
+    fn test_ismcts_goes_out_when_it_can() {
+        let hand = vec![card(3, Color::Red, Value::Number(2)), card(3, Color::Red, Value::Number(2))];
+        let view = view(hand, card(2, Color::Red, Value::Number(1)), 1);
+        let mut strategy = IsmctsStrategy::with_iterations(300, 7);
+        let BotAction::Play(cards) = strategy.choose_action(&view) else {
+            panic!("expected a play");
+        };
+        assert_eq!(cards.len(), 2);
+    }
+
+    #[test]
+    fn test_ismcts_chooses_legal_moves() {
+        let hand = vec![
+            card(53, Color::Wild, Value::Wild),
+            card(24, Color::Yellow, Value::Skip),
+            card(5, Color::Red, Value::Number(4)),
+        ];
+        let view = view(hand.clone(), card(2, Color::Red, Value::Number(1)), 3);
+        let mut strategy = IsmctsStrategy::with_iterations(200, 3);
+        match strategy.choose_action(&view) {
+            BotAction::Play(cards) => {
+                assert!(cards[0].can_be_played_on(&view.top_card));
+                assert!(cards.iter().all(|card| hand.iter().any(|held| held.id == card.id)));
+            }
+            BotAction::Draw => {}
+        }
+    }
+}
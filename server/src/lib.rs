@@ -0,0 +1,46 @@
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+static GAME_LOGGING: AtomicBool = AtomicBool::new(true);
+
+// Headless runs such as the simulator switch off the play-by-play logging of the game logic
+pub fn set_game_logging(enabled: bool) {
+    GAME_LOGGING.store(enabled, Ordering::Relaxed);
+}
+
+pub fn game_logging_enabled() -> bool {
+    GAME_LOGGING.load(Ordering::Relaxed)
+}
+
+// println! for the game logic, silenced by set_game_logging(false)
+#[macro_export]
+macro_rules! game_log {
+    ($($arg:tt)*) => {
+        if $crate::game_logging_enabled() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod card;
+pub mod chat;
+pub mod deck;
+pub mod environment;
+pub mod fair_shuffle;
+pub mod game_record;
+pub mod game_settings;
+pub mod game_state;
+pub mod ismcts;
+pub mod lobby;
+pub mod matchmaking;
+pub mod player;
+pub mod playerpool;
+pub mod strategy;
+pub mod websocket;
+pub mod game_state_player_management;
+pub mod game_state_card_management;
+pub mod game_state_bots;
+pub mod game_state_chat;
+pub mod game_state_rematch;
+pub mod game_state_events;
+#[cfg(test)]
+mod test_fixtures;
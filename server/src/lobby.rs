@@ -1,21 +1,85 @@
-use std::{ collections::HashMap, sync::Arc };
+use std::{ collections::{ HashMap, VecDeque }, sync::Arc, time::{ Duration, Instant } };
 
-use crate::{ game_state::GameState, player::Player, playerpool::PlayerPool };
+use crate::{
+    chat::{ sanitize_message, ChatHistory, ChatMessage },
+    game_settings::{ GameSettings, Visibility },
+    game_state::{ GameState, MatchResult, MIN_PLAYERS },
+    game_state_bots::{ BotTurn, BOT_MOVE_DELAY },
+    matchmaking::{ MatchPreferences, MatchmakingQueue, QueueEntry },
+    game_state_player_management::serialize_player_data,
+    player::{ OnlinePlayer, Player },
+    playerpool::PlayerPool,
+    strategy::BotAction,
+    websocket::create_websocket_message,
+};
+use futures::future::join_all;
+use rand::{ rngs::StdRng, seq::SliceRandom, Rng, SeedableRng };
 use serde_json::json;
-use tokio::sync::Mutex;
+use tokio::sync::{ mpsc::Sender, Mutex };
+
+const INVITE_CODE_LENGTH: usize = 6;
+// no 0/O or 1/I so codes can be read out loud
+const INVITE_CODE_CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const MAX_PASSWORD_LENGTH: usize = 64;
+// how long an empty table is kept around for players to come back to
+pub const EMPTY_GAME_GRACE_PERIOD: Duration = Duration::from_secs(60);
+pub const IDLE_GAME_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const ARCHIVE_LENGTH: usize = 100;
+pub const INVITATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+// A pending invitation from a player at a table to someone in the lobby
+#[derive(Debug)]
+pub struct Invitation {
+    pub id: usize,
+    pub game_id: usize,
+    pub from_id: usize,
+    pub to_id: usize,
+    pub created_at: Instant,
+}
 
 pub struct Lobby {
     pub games: HashMap<usize, GameState>, // Mapping of game IDs to game states
     next_game_id: usize,
     player_pool: Arc<Mutex<PlayerPool>>,
+    chat_history: ChatHistory,
+    pub archived_matches: VecDeque<MatchResult>, // most recent last
+    pub invitations: Vec<Invitation>,
+    next_invitation_id: usize,
+    pub matchmaking_queue: MatchmakingQueue,
+    rng: StdRng, // player ids, invite codes and the seed of every new table come from here
 }
 
 impl Lobby {
     pub fn new(player_pool: Arc<Mutex<PlayerPool>>) -> Self {
+        Self::with_rng(player_pool, StdRng::from_entropy())
+    }
+
+    //a lobby whose ids, invite codes and table seeds all follow from one seed, for tests and replays
+    pub fn with_seed(player_pool: Arc<Mutex<PlayerPool>>, seed: u64) -> Self {
+        Self::with_rng(player_pool, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(player_pool: Arc<Mutex<PlayerPool>>, rng: StdRng) -> Self {
         Self {
             games: HashMap::new(),
             next_game_id: 1,
             player_pool,
+            chat_history: ChatHistory::new(),
+            archived_matches: VecDeque::new(),
+            invitations: Vec::new(),
+            next_invitation_id: 1,
+            matchmaking_queue: MatchmakingQueue::new(),
+            rng,
+        }
+    }
+
+    pub async fn generate_player_id(&mut self) -> usize {
+        let player_pool = self.player_pool.lock().await;
+        loop {
+            let player_id = self.rng.gen();
+            if player_pool.get_player_by_id(player_id).is_none() {
+                return player_id;
+            }
         }
     }
 
@@ -28,11 +92,26 @@ impl Lobby {
             .map(|conn| conn.player.clone())
             .collect::<Vec<Player>>();
 
-        let players_not_in_game = players_in_pool
+        players_in_pool
             .into_iter()
             .filter(|player| player.current_game.is_none())
-            .collect();
-        players_not_in_game
+            .collect()
+    }
+
+    pub async fn list_online_players(&self) -> Vec<OnlinePlayer> {
+        let player_pool = self.player_pool.lock().await;
+        player_pool.connections
+            .iter()
+            .map(|conn| conn.player.to_online_player())
+            .collect()
+    }
+
+    //tell everyone who is online and what they are up to
+    pub async fn broadcast_presence(&self) {
+        let online_players = self.list_online_players().await;
+        let online_players_json = serde_json::to_string(&online_players).unwrap();
+        let message = create_websocket_message("update_online_players", &online_players_json);
+        self.player_pool.lock().await.broadcast_message(message).await;
     }
 
     pub async fn broadcast_lobby_gamelist(&self) -> Result<(), &'static str> {
@@ -58,27 +137,688 @@ impl Lobby {
         Ok(())
     }
 
-    pub async fn create_game(&mut self) -> usize {
+    //the creator is seated at the new table straight away and hosts it
+    pub async fn create_game(
+        &mut self,
+        host_id: usize,
+        tx: Sender<String>,
+        settings: GameSettings,
+        password: Option<String>
+    ) -> Result<usize, String> {
+        settings.validate()?;
+        let password = password.filter(|password| !password.is_empty());
+        if password.as_ref().is_some_and(|password| password.chars().count() > MAX_PASSWORD_LENGTH) {
+            return Err(format!("Password must be at most {} characters", MAX_PASSWORD_LENGTH));
+        }
         let game_id = self.next_game_id;
 
         //create a new player_pool for this game
         let game_player_pool = PlayerPool::new();
-        self.games.insert(game_id, GameState::new(game_id, game_player_pool));
+        let mut game = GameState::new(game_id, game_player_pool, host_id, settings);
+        game.reseed(self.rng.gen());
+        if game.settings.visibility == Visibility::Private {
+            game.invite_code = Some(self.generate_invite_code());
+        }
+        game.password = password;
+        self.games.insert(game_id, game);
         self.next_game_id += 1;
 
+        //a table whose host never sat down could not be started by anyone
+        if let Err(err) = self.join_game(game_id, host_id, tx, false).await {
+            self.games.remove(&game_id);
+            return Err(err);
+        }
+
+        Ok(game_id)
+    }
+
+    pub fn find_game_by_invite_code(&self, invite_code: &str) -> Option<usize> {
+        let invite_code = invite_code.trim().to_uppercase();
+        self.games
+            .values()
+            .find(|game| game.invite_code.as_deref() == Some(invite_code.as_str()))
+            .map(|game| game.id)
+    }
+
+    //private games need their invite code, password protected games their password
+    pub fn authorize_join(
+        &self,
+        game_id: usize,
+        invite_code: Option<&str>,
+        password: Option<&str>
+    ) -> Result<(), &'static str> {
+        let game = self.games.get(&game_id).ok_or("Game not found")?;
+        if let Some(expected_code) = &game.invite_code {
+            let code_matches = invite_code.is_some_and(
+                |code| code.trim().eq_ignore_ascii_case(expected_code)
+            );
+            if !code_matches {
+                return Err("Invalid invite code");
+            }
+        }
+        if let Some(expected_password) = &game.password {
+            if password != Some(expected_password.as_str()) {
+                return Err("Incorrect password");
+            }
+        }
+        Ok(())
+    }
+
+    fn generate_invite_code(&mut self) -> String {
+        loop {
+            let code: String = (0..INVITE_CODE_LENGTH)
+                .map(|_| *INVITE_CODE_CHARSET.choose(&mut self.rng).unwrap() as char)
+                .collect();
+            if self.find_game_by_invite_code(&code).is_none() {
+                return code;
+            }
+        }
+    }
+
+    //seat (or, for spectators, attach) a player to a game. The player's current game in the global pool is
+    //rolled back if the game turns them away
+    pub async fn join_game(
+        &mut self,
+        game_id: usize,
+        player_id: usize,
+        tx: Sender<String>,
+        as_spectator: bool
+    ) -> Result<(), String> {
+        let game = self.games.get_mut(&game_id).ok_or("Game not found")?;
+
+        //picking a table by hand takes the player out of quick play
+        if self.matchmaking_queue.remove(player_id).is_some() {
+            game_log!("Player {} left the matchmaking queue to join game {}", player_id, game_id);
+        }
+
+        let player = {
+            let mut player_pool = self.player_pool.lock().await;
+            let mut player = player_pool.get_player_by_id(player_id).ok_or("Player not found")?;
+            if as_spectator {
+                player.spectate_game(game_id)?;
+            } else {
+                player.join_game(game_id)?;
+            }
+            player_pool.update_player(player.clone());
+            player
+        };
+
+        let result = if as_spectator {
+            game.add_spectator(tx, player).await
+        } else {
+            game.add_player(tx, player).await
+        };
+        if let Err(err) = result {
+            self.release_player(player_id).await;
+            return Err(err.to_string());
+        }
+        //the table may have numbered the name to tell it apart; everyone online sees the same one
+        if let Some(seated) = self.games[&game_id].game_player_pool.get_player_by_id(player_id) {
+            let mut player_pool = self.player_pool.lock().await;
+            if let Some(mut player) = player_pool.get_player_by_id(player_id) {
+                player.name = seated.name;
+                player_pool.update_player(player);
+            }
+        }
+
+        let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+        Ok(())
+    }
+
+    pub async fn kick_player(
+        &mut self,
+        game_id: usize,
+        host_id: usize,
+        target_id: usize
+    ) -> Result<(), &'static str> {
+        let game = self.games.get_mut(&game_id).ok_or("Game not found")?;
+        game.kick_player(host_id, target_id).await?;
+        let _ = game.update_game_state().await;
+        self.release_player(target_id).await;
+        let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+        Ok(())
+    }
+
+    pub async fn leave_game(&mut self, game_id: usize, player_id: usize) -> Result<(), &'static str> {
+        let game = self.games.get_mut(&game_id).ok_or("Game not found")?;
+        game.remove_player(player_id).await?;
+        let _ = game.update_game_state().await;
+        self.release_player(player_id).await;
         let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+        Ok(())
+    }
+
+    //closing a game sends every player in it back to the lobby
+    pub async fn close_game(&mut self, game_id: usize, host_id: usize) -> Result<(), &'static str> {
+        let game = self.games.get(&game_id).ok_or("Game not found")?;
+        if !game.is_host(host_id) {
+            return Err("Only the host can close the game");
+        }
 
-        game_id
+        self.remove_game(game_id).await;
+        let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+        Ok(())
+    }
+
+    //drop a game from the lobby, sending anyone still at the table back to the lobby
+    async fn remove_game(&mut self, game_id: usize) {
+        let Some(mut game) = self.games.remove(&game_id) else {
+            return;
+        };
+        archive_matches(&mut self.archived_matches, &mut game);
+
+        let message = create_websocket_message("game_closed", &game_id.to_string());
+        game.game_player_pool.broadcast_message(message).await;
+        for conn in &game.game_player_pool.connections {
+            self.release_player(conn.player.id).await;
+        }
+    }
+
+    //periodic sweep: archive finished matches, and remove games that have been empty past the grace
+    //period or have seen no action for too long
+    pub async fn run_housekeeping(&mut self, now: Instant) {
+        self.expire_invitations(now).await;
+
+        let expired_votes: Vec<usize> = self.games
+            .values()
+            .filter(|game| game.rematch_vote.as_ref().is_some_and(|vote| vote.is_expired(now)))
+            .map(|game| game.id)
+            .collect();
+        for game_id in expired_votes {
+            self.resolve_rematch(game_id).await;
+        }
+
+        let mut expired_games = Vec::new();
+        for (game_id, game) in self.games.iter_mut() {
+            archive_matches(&mut self.archived_matches, game);
+
+            let empty_expired = game.empty_since.is_some_and(
+                |empty_since| now.duration_since(empty_since) >= EMPTY_GAME_GRACE_PERIOD
+            );
+            //a paused game is waiting for its players on purpose
+            let idle_expired =
+                !game.is_paused && now.duration_since(game.last_activity) >= IDLE_GAME_TIMEOUT;
+            if empty_expired || idle_expired {
+                expired_games.push(*game_id);
+            }
+        }
+
+        if expired_games.is_empty() {
+            return;
+        }
+        for game_id in expired_games {
+            game_log!("Removing inactive game {}", game_id);
+            self.remove_game(game_id).await;
+        }
+        let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+    }
+
+    pub async fn invite_player(
+        &mut self,
+        from_id: usize,
+        game_id: usize,
+        to_id: usize
+    ) -> Result<(), &'static str> {
+        let game = self.games.get(&game_id).ok_or("Game not found")?;
+        if game.game_player_pool.get_player_by_id(from_id).is_none() {
+            return Err("You are not in that game");
+        }
+        if from_id == to_id {
+            return Err("You cannot invite yourself");
+        }
+        if self.invitations.iter().any(|invitation| invitation.game_id == game_id && invitation.to_id == to_id) {
+            return Err("Player has already been invited");
+        }
+
+        let player_pool = self.player_pool.lock().await;
+        let from = player_pool.get_player_by_id(from_id).ok_or("Player not found")?;
+        let to = player_pool.get_player_by_id(to_id).ok_or("Player is not online")?;
+        if to.current_game.is_some() {
+            return Err("Player is already in a game");
+        }
+
+        let invitation = Invitation {
+            id: self.next_invitation_id,
+            game_id,
+            from_id,
+            to_id,
+            created_at: Instant::now(),
+        };
+        self.next_invitation_id += 1;
+
+        let invitation_json = json!({
+            "invitation_id": invitation.id,
+            "game_id": game_id,
+            "game_name": game.settings.display_name(game_id),
+            "from_id": from_id,
+            "from_name": from.name,
+            "expires_in": INVITATION_TIMEOUT.as_secs(),
+        }).to_string();
+        let message = create_websocket_message("game_invitation", &invitation_json);
+        player_pool.send_message(&to, message).await;
+
+        self.invitations.push(invitation);
+        Ok(())
+    }
+
+    //accepting seats the player straight away - an invitation gets past private and password checks
+    pub async fn accept_invitation(
+        &mut self,
+        player_id: usize,
+        invitation_id: usize,
+        tx: Sender<String>
+    ) -> Result<usize, String> {
+        let invitation = self.take_invitation(player_id, invitation_id)?;
+        if invitation.created_at.elapsed() >= INVITATION_TIMEOUT {
+            return Err("Invitation has expired".to_string());
+        }
+        self.join_game(invitation.game_id, player_id, tx, false).await?;
+        self.notify_invitation_outcome(&invitation, "invitation_accepted").await;
+        Ok(invitation.game_id)
+    }
+
+    pub async fn decline_invitation(
+        &mut self,
+        player_id: usize,
+        invitation_id: usize
+    ) -> Result<(), &'static str> {
+        let invitation = self.take_invitation(player_id, invitation_id)?;
+        self.notify_invitation_outcome(&invitation, "invitation_declined").await;
+        Ok(())
+    }
+
+    fn take_invitation(&mut self, player_id: usize, invitation_id: usize) -> Result<Invitation, &'static str> {
+        let pos = self.invitations
+            .iter()
+            .position(|invitation| invitation.id == invitation_id && invitation.to_id == player_id)
+            .ok_or("Invitation not found")?;
+        Ok(self.invitations.remove(pos))
+    }
+
+    async fn expire_invitations(&mut self, now: Instant) {
+        let (expired, pending) = self.invitations
+            .drain(..)
+            .partition(|invitation| now.duration_since(invitation.created_at) >= INVITATION_TIMEOUT);
+        self.invitations = pending;
+        for invitation in expired {
+            self.notify_invitation_outcome(&invitation, "invitation_expired").await;
+        }
+    }
+
+    //both sides of an invitation hear how it ended
+    async fn notify_invitation_outcome(&self, invitation: &Invitation, sv: &str) {
+        let outcome_json = json!({
+            "invitation_id": invitation.id,
+            "game_id": invitation.game_id,
+            "from_id": invitation.from_id,
+            "to_id": invitation.to_id,
+        }).to_string();
+        let message = create_websocket_message(sv, &outcome_json);
+        let player_pool = self.player_pool.lock().await;
+        for player_id in [invitation.from_id, invitation.to_id] {
+            if let Some(player) = player_pool.get_player_by_id(player_id) {
+                player_pool.send_message(&player, message.clone()).await;
+            }
+        }
+    }
+
+    pub async fn join_queue(
+        &mut self,
+        player_id: usize,
+        tx: Sender<String>,
+        preferences: MatchPreferences
+    ) -> Result<(), String> {
+        preferences.validate()?;
+        if self.matchmaking_queue.contains(player_id) {
+            return Err("You are already in the queue".to_string());
+        }
+        {
+            let player_pool = self.player_pool.lock().await;
+            let player = player_pool.get_player_by_id(player_id).ok_or("Player not found")?;
+            if player.current_game.is_some() {
+                return Err("Player is already in a game".to_string());
+            }
+        }
+
+        self.matchmaking_queue.entries.push(QueueEntry {
+            player_id,
+            tx,
+            preferences: preferences.clone(),
+            queued_at: Instant::now(),
+        });
+
+        match self.matchmaking_queue.take_match(&preferences) {
+            Some(matched) => self.start_matched_game(matched, preferences).await,
+            None => self.send_queue_status(&preferences).await,
+        }
+        Ok(())
+    }
+
+    pub async fn leave_queue(&mut self, player_id: usize) -> Result<(), &'static str> {
+        let entry = self.matchmaking_queue.remove(player_id).ok_or("You are not in the queue")?;
+        self.send_queue_status(&entry.preferences).await;
+        Ok(())
+    }
+
+    //keep everyone waiting for the same kind of table up to date on how close a match is
+    async fn send_queue_status(&self, preferences: &MatchPreferences) {
+        let players_waiting = self.matchmaking_queue.count_matching(preferences);
+        for entry in &self.matchmaking_queue.entries {
+            if &entry.preferences != preferences {
+                continue;
+            }
+            let status_json = json!({
+                "status": "searching",
+                "players_waiting": players_waiting,
+                "players_needed": preferences.player_count,
+                "waiting_secs": entry.queued_at.elapsed().as_secs(),
+            }).to_string();
+            let message = create_websocket_message("matchmaking_status", &status_json);
+            let _ = entry.tx.send(message).await;
+        }
+    }
+
+    //create a table for a matched group, seat everyone and deal straight away
+    async fn start_matched_game(&mut self, matched: Vec<QueueEntry>, preferences: MatchPreferences) {
+        let host_id = matched[0].player_id;
+        let host_tx = matched[0].tx.clone();
+        let settings = GameSettings {
+            name: Some("Quick play".to_string()),
+            max_players: preferences.player_count,
+            rules: preferences.rules,
+            ..GameSettings::default()
+        };
+        let game_id = match self.create_game(host_id, host_tx, settings, None).await {
+            Ok(game_id) => game_id,
+            Err(err) => {
+                game_log!("Failed to create matchmaking game: {}", err);
+                return;
+            }
+        };
+
+        //the host was seated along with the table
+        for entry in matched {
+            let seated = if entry.player_id == host_id {
+                Ok(())
+            } else {
+                self.join_game(game_id, entry.player_id, entry.tx.clone(), false).await
+            };
+            if let Err(err) = seated {
+                game_log!("Failed to seat player {} from the queue: {}", entry.player_id, err);
+                continue;
+            }
+            let status_json = json!({
+                "status": "matched",
+                "game_id": game_id,
+            }).to_string();
+            let _ = entry.tx.send(create_websocket_message("matchmaking_status", &status_json)).await;
+            let _ = entry.tx.send(create_websocket_message("you_joined_game", &game_id.to_string())).await;
+        }
+
+        if let Some(game) = self.games.get_mut(&game_id) {
+            if let Err(err) = game.start_game(host_id).await {
+                game_log!("Failed to start matchmaking game {}: {}", game_id, err);
+            }
+        }
+        let _ = self.broadcast_lobby_gamelist().await;
+    }
+
+    pub async fn vote_rematch(
+        &mut self,
+        game_id: usize,
+        player_id: usize,
+        accept: bool
+    ) -> Result<(), &'static str> {
+        let game = self.games.get_mut(&game_id).ok_or("Game not found")?;
+        if game.cast_rematch_vote(player_id, accept).await? {
+            self.resolve_rematch(game_id).await;
+        }
+        Ok(())
+    }
+
+    //with enough players in favour, at least one of them a person, the table goes again; everyone
+    //who did not accept goes back to the lobby
+    async fn resolve_rematch(&mut self, game_id: usize) {
+        let Some(vote) = self.games.get_mut(&game_id).and_then(|game| game.rematch_vote.take()) else {
+            return;
+        };
+        let accepted = vote.accepted();
+        let rematch = accepted.len() >= MIN_PLAYERS && vote.accepted_by_a_player();
+        let leaving: Vec<usize> = vote.eligible
+            .iter()
+            .filter(|player_id| !rematch || !accepted.contains(player_id))
+            .copied()
+            .collect();
+
+        for player_id in leaving {
+            if let Some(game) = self.games.get_mut(&game_id) {
+                let _ = game.remove_player(player_id).await;
+            }
+            self.release_player(player_id).await;
+            let player_pool = self.player_pool.lock().await;
+            if let Some(player) = player_pool.get_player_by_id(player_id) {
+                let message = create_websocket_message("you_left_game", &game_id.to_string());
+                player_pool.send_message(&player, message).await;
+            }
+        }
+
+        if let Some(game) = self.games.get_mut(&game_id) {
+            if rematch {
+                game.start_rematch().await;
+            } else {
+                let _ = game.update_game_state().await;
+            }
+        }
+        let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+    }
+
+    //the bots whose turn has come up and who have had time to think. Players who have run out of
+    //time are moved for on the spot
+    pub async fn due_bot_turns(&mut self, now: Instant) -> Vec<BotTurn> {
+        let mut due = Vec::new();
+        for game in self.games.values_mut() {
+            if game.empty_since.is_some() {
+                continue;
+            }
+            if game.is_bot_turn() {
+                if now.duration_since(game.last_activity) >= BOT_MOVE_DELAY {
+                    due.extend(game.bot_turn());
+                }
+            } else if game.is_turn_timed_out(now) {
+                game.handle_turn_timeout().await;
+            }
+        }
+        due
+    }
+
+    pub async fn apply_bot_turn(&mut self, turn: &BotTurn, action: BotAction) {
+        if let Some(game) = self.games.get_mut(&turn.game_id) {
+            game.apply_bot_turn(turn, action).await;
+        }
+    }
+
+    //one tick of the bots: the lobby is only locked to see whose turn it is and to make the moves.
+    //Working the moves out - a hard bot searches for a while - happens on the blocking pool
+    pub async fn run_bot_turns(lobby: &Arc<Mutex<Lobby>>, now: Instant) {
+        let due = lobby.lock().await.due_bot_turns(now).await;
+        if due.is_empty() {
+            return;
+        }
+        let moves = join_all(
+            due.into_iter().map(|turn|
+                tokio::task::spawn_blocking(move || {
+                    let action = turn.choose_action();
+                    (turn, action)
+                })
+            )
+        ).await;
+
+        let mut lobby = lobby.lock().await;
+        for (turn, action) in moves.into_iter().flatten() {
+            lobby.apply_bot_turn(&turn, action).await;
+        }
+    }
+
+    //a closed socket. Where the table allows it a bot keeps a seat in a running round warm for the
+    //player to come back to; otherwise they leave the game
+    pub async fn disconnect_player(&mut self, player_id: usize) {
+        let _ = self.leave_queue(player_id).await;
+        for game in self.games.values_mut() {
+            let Some(player) = game.game_player_pool.get_player_by_id(player_id) else {
+                continue;
+            };
+            let keep_seat = game.settings.bot_takeover && game.round_in_progress && !player.is_spectator;
+            if keep_seat {
+                game.game_player_pool.disconnect(player_id);
+                let taken_over = player.is_bot_controlled || game.take_over_seat(player_id).await.is_ok();
+                if taken_over {
+                    game_log!("Player {} disconnected, a bot is playing their seat in game {}", player_id, game.id);
+                    if !game.has_human_players() {
+                        game.empty_since = Some(Instant::now());
+                    }
+                    continue;
+                }
+            }
+            game_log!("Player {} found and removed from game {}", player_id, game.id);
+            let _ = game.remove_player(player_id).await;
+            let _ = game.update_game_state().await;
+        }
+        let _ = self.broadcast_lobby_gamelist().await;
+        self.broadcast_presence().await;
+    }
+
+    //a reconnected client takes back the seat a bot has been playing for them. Returns the player
+    //whose identity the connection now has
+    pub async fn resume_seat(&mut self, resume_token: &str, tx: Sender<String>) -> Result<Player, &'static str> {
+        if resume_token.is_empty() {
+            return Err("No seat to resume");
+        }
+        let game = self.games
+            .values_mut()
+            .find(|game| {
+                game.game_player_pool.connections
+                    .iter()
+                    .any(|conn| {
+                        conn.player.is_bot_controlled &&
+                            !conn.is_connected() &&
+                            conn.player.resume_token == resume_token
+                    })
+            })
+            .ok_or("No seat to resume")?;
+        let player_id = game.game_player_pool.connections
+            .iter()
+            .find(|conn| conn.player.resume_token == resume_token)
+            .map(|conn| conn.player.id)
+            .unwrap();
+        game.resume_control(player_id, Some(tx)).await?;
+        Ok(game.game_player_pool.get_player_by_id(player_id).unwrap())
+    }
+
+    pub fn list_archived_matches(&self) -> Vec<MatchResult> {
+        self.archived_matches.iter().rev().cloned().collect()
+    }
+
+    //change a player's name and/or avatar and color. Fields that are not given keep their current value
+    pub async fn set_profile(
+        &mut self,
+        player_id: usize,
+        name: Option<&str>,
+        avatar: Option<String>,
+        color: Option<String>
+    ) -> Result<(), String> {
+        let player = {
+            let mut player_pool = self.player_pool.lock().await;
+            let mut player = player_pool.get_player_by_id(player_id).ok_or("Player not found")?;
+            if let Some(name) = name {
+                player.set_name(name)?;
+            }
+            let avatar = avatar.or(player.avatar.clone());
+            let color = color.or(player.color.clone());
+            player.set_profile(avatar, color)?;
+
+            if let Some(game) = player.current_game.and_then(|game_id| self.games.get(&game_id)) {
+                if game.is_name_taken(&player.name, player_id) {
+                    return Err("That name is already taken in this game".to_string());
+                }
+            }
+            player_pool.update_player(player.clone());
+
+            //players in a game get their update from the game, which knows their hand
+            if player.current_game.is_none() {
+                let message = create_websocket_message("update_player", &serialize_player_data(&player));
+                player_pool.send_message(&player, message).await;
+            }
+            player
+        };
+
+        if let Some(game) = player.current_game.and_then(|game_id| self.games.get_mut(&game_id)) {
+            game.update_player_profile(&player).await;
+        }
+        self.broadcast_presence().await;
+        Ok(())
+    }
+
+    //clear the player's current game in the global pool so they show up in the lobby again
+    async fn release_player(&self, player_id: usize) {
+        let mut player_pool = self.player_pool.lock().await;
+        if let Some(mut player) = player_pool.get_player_by_id(player_id) {
+            let _ = player.leave_game();
+            player_pool.update_player(player);
+        }
+    }
+
+    //lobby chat goes out to everyone who is connected
+    pub async fn send_chat(&mut self, player_id: usize, text: &str) -> Result<(), &'static str> {
+        let player_pool = self.player_pool.lock().await;
+        let player = player_pool.get_player_by_id(player_id).ok_or("Player not found")?;
+        let text = sanitize_message(text)?;
+
+        let chat_message = ChatMessage::new(player_id, player.name, text);
+        self.chat_history.push(chat_message.clone());
+
+        let chat_json = json!({
+            "scope": "lobby",
+            "message": chat_message,
+        }).to_string();
+        let message = create_websocket_message("chat_message", &chat_json);
+        player_pool.broadcast_message(message).await;
+        Ok(())
+    }
+
+    pub fn chat_history_message(&self) -> String {
+        let history_json = json!({
+            "scope": "lobby",
+            "messages": self.chat_history.messages,
+        }).to_string();
+        create_websocket_message("chat_history", &history_json)
     }
 
     // list all games in the lobby with details about player count and round in progress
     pub fn list_games(&self) -> Vec<serde_json::Value> {
         let mut games = Vec::new();
         for (game_id, game_state) in &self.games {
+            //private games are only reachable through their invite code
+            if game_state.settings.visibility == Visibility::Private {
+                continue;
+            }
             let game =
                 json!({
                 "id": game_id,
+                "name": game_state.settings.display_name(*game_id),
                 "player_count": game_state.get_player_count(),
+                "max_players": game_state.settings.max_players,
+                "hand_size": game_state.settings.hand_size,
+                "score_target": game_state.settings.score_target,
+                "rules": game_state.settings.rules,
+                "visibility": game_state.settings.visibility,
+                "has_password": game_state.password.is_some(),
+                "host_id": game_state.host_id,
                 "round_in_progress": game_state.round_in_progress
             });
             games.push(game);
@@ -86,3 +826,271 @@ impl Lobby {
         games
     }
 }
+
+fn archive_matches(archive: &mut VecDeque<MatchResult>, game: &mut GameState) {
+    for match_result in game.finished_matches.drain(..) {
+        if archive.len() == ARCHIVE_LENGTH {
+            archive.pop_front();
+        }
+        archive.push_back(match_result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::BotDifficulty;
+
+    fn new_lobby() -> Lobby {
+        Lobby::new(Arc::new(Mutex::new(PlayerPool::new())))
+    }
+
+    //put a player online in the lobby, returning the sender their game messages go to
+    async fn connect(lobby: &Lobby, player_id: usize) -> Sender<String> {
+        let (tx, _) = tokio::sync::mpsc::channel::<String>(1);
+        lobby.player_pool.lock().await.register_connection(tx.clone(), Player::new(player_id));
+        tx
+    }
+
+    #[tokio::test]
+    async fn test_private_game_requires_invite_code() {
+        let mut lobby = new_lobby();
+        let settings = GameSettings { visibility: Visibility::Private, ..GameSettings::default() };
+        let tx = connect(&lobby, 1).await;
+        let game_id = lobby.create_game(1, tx, settings, None).await.unwrap();
+        let code = lobby.games[&game_id].invite_code.clone().unwrap();
+
+        assert!(lobby.list_games().is_empty());
+        assert_eq!(lobby.find_game_by_invite_code(&code.to_lowercase()), Some(game_id));
+        assert_eq!(lobby.authorize_join(game_id, None, None), Err("Invalid invite code"));
+        assert_eq!(lobby.authorize_join(game_id, Some("WRONG1"), None), Err("Invalid invite code"));
+        assert!(lobby.authorize_join(game_id, Some(&code), None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_lobbies_reproduce_games() {
+        let mut lobbies = [
+            Lobby::with_seed(Arc::new(Mutex::new(PlayerPool::new())), 42),
+            Lobby::with_seed(Arc::new(Mutex::new(PlayerPool::new())), 42),
+        ];
+        let settings = GameSettings { visibility: Visibility::Private, ..GameSettings::default() };
+        let mut games = Vec::new();
+        for lobby in &mut lobbies {
+            let player_id = lobby.generate_player_id().await;
+            let tx = connect(lobby, player_id).await;
+            let game_id = lobby.create_game(player_id, tx, settings.clone(), None).await.unwrap();
+            games.push(lobby.games.remove(&game_id).unwrap());
+        }
+
+        assert_eq!(games[0].host_id, games[1].host_id);
+        assert_eq!(games[0].invite_code, games[1].invite_code);
+        assert_eq!(games[0].seed, games[1].seed);
+        assert_eq!(games[0].deck.cards, games[1].deck.cards);
+
+        let seed = games[0].seed;
+        games[1].reseed(seed + 1);
+        assert_ne!(games[0].deck.cards, games[1].deck.cards);
+        games[1].reseed(seed);
+        assert_eq!(games[0].deck.cards, games[1].deck.cards);
+    }
+
+    #[tokio::test]
+    async fn test_password_protected_game() {
+        let mut lobby = new_lobby();
+        let tx = connect(&lobby, 1).await;
+        let game_id = lobby
+            .create_game(1, tx, GameSettings::default(), Some("hunter2".to_string())).await
+            .unwrap();
+
+        assert_eq!(lobby.list_games()[0]["has_password"], true);
+        //the creator was seated without needing the password and hosts the table
+        assert_eq!(lobby.games[&game_id].get_player_count(), 1);
+        assert!(lobby.games[&game_id].is_host(1));
+        assert_eq!(lobby.authorize_join(game_id, None, None), Err("Incorrect password"));
+        assert_eq!(lobby.authorize_join(game_id, None, Some("hunter3")), Err("Incorrect password"));
+        assert!(lobby.authorize_join(game_id, None, Some("hunter2")).is_ok());
+    }
+
+    async fn lobby_with_table(players: usize) -> (Lobby, usize) {
+        let mut lobby = new_lobby();
+        let tx = connect(&lobby, 1).await;
+        let game_id = lobby.create_game(1, tx, GameSettings::default(), None).await.unwrap();
+        for id in 2..=players {
+            let tx = connect(&lobby, id).await;
+            lobby.join_game(game_id, id, tx, false).await.unwrap();
+        }
+        (lobby, game_id)
+    }
+
+    #[tokio::test]
+    async fn test_numbered_name_reaches_the_lobby() {
+        let (mut lobby, game_id) = lobby_with_table(1).await;
+        let tx = connect(&lobby, 2).await;
+        lobby.set_profile(2, Some("Player 1"), None, None).await.unwrap();
+
+        lobby.join_game(game_id, 2, tx, false).await.unwrap();
+
+        let seated = lobby.games[&game_id].game_player_pool.get_player_by_id(2).unwrap();
+        assert_eq!(seated.name, "Player 1 2");
+        let online = lobby.player_pool.lock().await.get_player_by_id(2).unwrap();
+        assert_eq!(online.name, seated.name);
+    }
+
+    #[tokio::test]
+    async fn test_leaving_between_rounds_frees_the_player() {
+        let (mut lobby, game_id) = lobby_with_table(2).await;
+
+        lobby.leave_game(game_id, 2).await.unwrap();
+
+        assert_eq!(lobby.games[&game_id].get_player_count(), 1);
+        let player = lobby.player_pool.lock().await.get_player_by_id(2).unwrap();
+        assert!(player.current_game.is_none());
+        assert!(lobby.leave_game(game_id, 2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_leaving_mid_round_returns_the_hand() {
+        let (mut lobby, game_id) = lobby_with_table(3).await;
+        lobby.games.get_mut(&game_id).unwrap().start_game(1).await.unwrap();
+
+        lobby.leave_game(game_id, 3).await.unwrap();
+
+        let game = &lobby.games[&game_id];
+        assert!(game.round_in_progress);
+        let in_hands: usize = game.get_all_players_in_game()
+            .iter()
+            .map(|player| player.hand.len())
+            .sum();
+        assert_eq!(game.deck.cards.len() + game.discard_pile.len() + in_hands, 108);
+        let player = lobby.player_pool.lock().await.get_player_by_id(3).unwrap();
+        assert!(player.current_game.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_only_the_host_closes_a_game() {
+        let (mut lobby, game_id) = lobby_with_table(2).await;
+
+        assert_eq!(lobby.close_game(game_id, 2).await, Err("Only the host can close the game"));
+        lobby.close_game(game_id, 1).await.unwrap();
+        assert!(lobby.games.is_empty());
+        assert!(lobby.player_pool.lock().await.get_player_by_id(2).unwrap().current_game.is_none());
+        assert_eq!(lobby.close_game(game_id, 1).await, Err("Game not found"));
+    }
+
+    #[tokio::test]
+    async fn test_housekeeping_removes_empty_and_idle_games() {
+        let mut lobby = new_lobby();
+        let tx = connect(&lobby, 1).await;
+        let empty_game = lobby.create_game(1, tx, GameSettings::default(), None).await.unwrap();
+        lobby.leave_game(empty_game, 1).await.unwrap();
+        let tx = connect(&lobby, 2).await;
+        let idle_game = lobby.create_game(2, tx, GameSettings::default(), None).await.unwrap();
+
+        let now = Instant::now();
+        lobby.run_housekeeping(now).await;
+        assert_eq!(lobby.games.len(), 2);
+
+        lobby.run_housekeeping(now + EMPTY_GAME_GRACE_PERIOD).await;
+        assert!(!lobby.games.contains_key(&empty_game));
+        assert!(lobby.games.contains_key(&idle_game));
+
+        lobby.run_housekeeping(now + IDLE_GAME_TIMEOUT).await;
+        assert!(lobby.games.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invitation_flow() {
+        let mut lobby = new_lobby();
+        let settings = GameSettings { visibility: Visibility::Private, ..GameSettings::default() };
+        let tx = connect(&lobby, 1).await;
+        let game_id = lobby.create_game(1, tx, settings, None).await.unwrap();
+        for id in 2..=3 {
+            connect(&lobby, id).await;
+        }
+
+        assert_eq!(lobby.invite_player(2, game_id, 3).await, Err("You are not in that game"));
+        lobby.invite_player(1, game_id, 2).await.unwrap();
+        lobby.invite_player(1, game_id, 3).await.unwrap();
+        assert_eq!(lobby.invite_player(1, game_id, 2).await, Err("Player has already been invited"));
+
+        //the invitation gets player 2 into the private game without the code
+        let invitation_id = lobby.invitations[0].id;
+        let (tx, _) = tokio::sync::mpsc::channel::<String>(1);
+        assert_eq!(lobby.accept_invitation(2, invitation_id, tx).await, Ok(game_id));
+        assert_eq!(lobby.games[&game_id].get_player_count(), 2);
+
+        lobby.run_housekeeping(Instant::now() + INVITATION_TIMEOUT).await;
+        assert!(lobby.invitations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_matchmaking_starts_game_when_queue_fills() {
+        let mut lobby = new_lobby();
+        for id in 1..=2 {
+            let (tx, _) = tokio::sync::mpsc::channel::<String>(1);
+            lobby.player_pool.lock().await.register_connection(tx, Player::new(id));
+        }
+
+        let (tx, _) = tokio::sync::mpsc::channel::<String>(1);
+        lobby.join_queue(1, tx, MatchPreferences::default()).await.unwrap();
+        assert!(lobby.games.is_empty());
+
+        let (tx, _) = tokio::sync::mpsc::channel::<String>(1);
+        lobby.join_queue(2, tx, MatchPreferences::default()).await.unwrap();
+        assert!(lobby.matchmaking_queue.entries.is_empty());
+        let game = lobby.games.values().next().unwrap();
+        assert_eq!(game.get_player_count(), 2);
+        assert!(game.round_in_progress);
+    }
+
+    #[tokio::test]
+    async fn test_rematch_vote_reseats_accepting_players() {
+        let mut lobby = new_lobby();
+        let settings = GameSettings { score_target: 0, ..GameSettings::default() };
+        let tx = connect(&lobby, 1).await;
+        let game_id = lobby.create_game(1, tx, settings, None).await.unwrap();
+        for id in 2..=3 {
+            let tx = connect(&lobby, id).await;
+            lobby.join_game(game_id, id, tx, false).await.unwrap();
+        }
+        let game = lobby.games.get_mut(&game_id).unwrap();
+        game.start_game(1).await.unwrap();
+        let seed = game.seed;
+        game.finish_round(1).await;
+        assert!(game.rematch_vote.is_some());
+        //the finished match publishes its seed and the rematch gets a fresh one
+        assert_eq!(game.finished_matches[0].seed, seed);
+        assert_ne!(game.seed, seed);
+
+        lobby.vote_rematch(game_id, 1, true).await.unwrap();
+        lobby.vote_rematch(game_id, 3, false).await.unwrap();
+        assert!(!lobby.games[&game_id].round_in_progress);
+        lobby.vote_rematch(game_id, 2, true).await.unwrap();
+
+        let game = &lobby.games[&game_id];
+        assert!(game.rematch_vote.is_none());
+        assert!(game.round_in_progress);
+        let seats: Vec<usize> = game.get_all_players_in_game().iter().map(|p| p.id).collect();
+        assert_eq!(seats, vec![2, 1]);
+        let player_3 = lobby.player_pool.lock().await.get_player_by_id(3).unwrap();
+        assert!(player_3.current_game.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bots_alone_do_not_start_a_rematch() {
+        let mut lobby = new_lobby();
+        let settings = GameSettings { score_target: 0, ..GameSettings::default() };
+        let tx = connect(&lobby, 1).await;
+        let game_id = lobby.create_game(1, tx, settings, None).await.unwrap();
+        let game = lobby.games.get_mut(&game_id).unwrap();
+        game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+        game.add_bot(1, BotDifficulty::Easy).await.unwrap();
+        game.start_game(1).await.unwrap();
+        game.finish_round(1).await;
+
+        lobby.vote_rematch(game_id, 1, false).await.unwrap();
+        let game = &lobby.games[&game_id];
+        assert!(game.rematch_vote.is_none());
+        assert!(!game.round_in_progress);
+    }
+}
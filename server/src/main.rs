@@ -1,26 +1,40 @@
-mod card;
-mod deck;
-mod game_state;
-mod lobby;
-mod player;
-mod playerpool;
-mod websocket;
-mod game_state_player_management;
-mod game_state_card_management;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::playerpool::PlayerPool;
-use lobby::Lobby;
+use server::{ lobby::Lobby, playerpool::PlayerPool, websocket };
 use tokio::sync::Mutex;
 use warp::ws::WebSocket;
 use warp::Filter;
 
+const HOUSEKEEPING_INTERVAL_SECS: u64 = 10;
+const BOT_TICK_MILLIS: u64 = 250;
+
 #[tokio::main]
 async fn main() {
     // Wrap the Lobby and PlayerPool in an Arc<Mutex<...>>
     let player_pool = Arc::new(Mutex::new(PlayerPool::new()));
     let lobby = Arc::new(Mutex::new(Lobby::new(player_pool.clone()))); // Clone player_pool here
 
+    // Periodically clear out abandoned games
+    let housekeeping_lobby = lobby.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(HOUSEKEEPING_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            housekeeping_lobby.lock().await.run_housekeeping(std::time::Instant::now()).await;
+        }
+    });
+
+    // Drive the bots sitting at any of the tables
+    let bot_lobby = lobby.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(BOT_TICK_MILLIS));
+        loop {
+            interval.tick().await;
+            Lobby::run_bot_turns(&bot_lobby, std::time::Instant::now()).await;
+        }
+    });
+
     println!("Server running on http://127.0.0.1:3030");
     let ws_route = warp
         ::ws()
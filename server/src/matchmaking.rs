@@ -0,0 +1,128 @@
+use std::time::Instant;
+
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::{ game_settings::Ruleset, game_state::{ MAX_PLAYERS, MIN_PLAYERS } };
+
+// What a player is looking for when they enter quick play
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MatchPreferences {
+    pub player_count: usize,
+    pub rules: Ruleset,
+}
+
+impl Default for MatchPreferences {
+    fn default() -> Self {
+        Self {
+            player_count: 2,
+            rules: Ruleset::default(),
+        }
+    }
+}
+
+impl MatchPreferences {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.player_count < MIN_PLAYERS || self.player_count > MAX_PLAYERS {
+            return Err(format!("Player count must be between {} and {}", MIN_PLAYERS, MAX_PLAYERS));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueEntry {
+    pub player_id: usize,
+    pub tx: Sender<String>,
+    pub preferences: MatchPreferences,
+    pub queued_at: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct MatchmakingQueue {
+    pub entries: Vec<QueueEntry>, // oldest first
+}
+
+impl MatchmakingQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, player_id: usize) -> bool {
+        self.entries.iter().any(|entry| entry.player_id == player_id)
+    }
+
+    pub fn remove(&mut self, player_id: usize) -> Option<QueueEntry> {
+        let pos = self.entries.iter().position(|entry| entry.player_id == player_id)?;
+        Some(self.entries.remove(pos))
+    }
+
+    pub fn count_matching(&self, preferences: &MatchPreferences) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| &entry.preferences == preferences)
+            .count()
+    }
+
+    //take the longest-waiting group of players with these preferences, once there are enough of them
+    pub fn take_match(&mut self, preferences: &MatchPreferences) -> Option<Vec<QueueEntry>> {
+        if self.count_matching(preferences) < preferences.player_count {
+            return None;
+        }
+        let mut matched = Vec::new();
+        let mut remaining = Vec::new();
+        for entry in self.entries.drain(..) {
+            if matched.len() < preferences.player_count && &entry.preferences == preferences {
+                matched.push(entry);
+            } else {
+                remaining.push(entry);
+            }
+        }
+        self.entries = remaining;
+        Some(matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn entry(player_id: usize, preferences: &MatchPreferences) -> QueueEntry {
+        let (tx, _) = mpsc::channel::<String>(1);
+        QueueEntry {
+            player_id,
+            tx,
+            preferences: preferences.clone(),
+            queued_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_take_match_groups_matching_preferences() {
+        let three_players = MatchPreferences { player_count: 3, ..MatchPreferences::default() };
+        let two_players = MatchPreferences::default();
+
+        let mut queue = MatchmakingQueue::new();
+        queue.entries.push(entry(1, &three_players));
+        queue.entries.push(entry(2, &two_players));
+        queue.entries.push(entry(3, &three_players));
+        assert!(queue.take_match(&three_players).is_none());
+
+        queue.entries.push(entry(4, &three_players));
+        queue.entries.push(entry(5, &three_players));
+        let matched = queue.take_match(&three_players).unwrap();
+        let ids: Vec<usize> = matched.iter().map(|entry| entry.player_id).collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert!(queue.contains(2));
+        assert!(queue.contains(5));
+    }
+
+    #[test]
+    fn test_preferences_validation() {
+        assert!(MatchPreferences::default().validate().is_ok());
+        let preferences = MatchPreferences { player_count: 1, ..MatchPreferences::default() };
+        assert!(preferences.validate().is_err());
+    }
+}
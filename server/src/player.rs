@@ -1,6 +1,8 @@
-use crate::card::Card;
+use crate::{ card::Card, game_settings::MAX_NAME_LENGTH };
 use serde::Serialize;
 
+const MAX_AVATAR_LENGTH: usize = 32;
+
 #[derive(Clone, Debug)]
 pub struct Player {
     pub id: usize,
@@ -8,13 +10,46 @@ pub struct Player {
     pub hand: Vec<Card>,
     pub current_game: Option<usize>, // Game ID or reference to the current game
     pub is_spectator: bool,
+    pub is_ready: bool,
+    pub is_waiting_for_seat: bool, // joined as a player mid-round; takes a seat when the next round starts
+    pub score: i32,
+    pub avatar: Option<String>, // name of one of the client's avatar images
+    pub color: Option<String>, // #rrggbb
+    pub is_bot: bool,
+    pub is_bot_controlled: bool, // a bot is playing this seat while the player is away
+    pub resume_token: String, // lets the player take their seat back after reconnecting
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Debug)]
 pub struct SerializablePlayer {
     pub id: usize,
     pub name: String,
     pub card_count: usize,
+    pub is_ready: bool,
+    pub score: i32,
+    pub avatar: Option<String>,
+    pub color: Option<String>,
+    pub is_bot: bool,
+    pub is_bot_controlled: bool,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayerStatus {
+    Idle,
+    InGame,
+    Spectating,
+}
+
+// An entry in the lobby's list of who is online
+#[derive(Serialize, Clone, Debug)]
+pub struct OnlinePlayer {
+    pub id: usize,
+    pub name: String,
+    pub avatar: Option<String>,
+    pub color: Option<String>,
+    pub status: PlayerStatus,
+    pub game_id: Option<usize>,
 }
 
 impl Player {
@@ -25,6 +60,14 @@ impl Player {
             hand: Vec::new(),
             current_game: None,
             is_spectator: false,
+            is_ready: false,
+            is_waiting_for_seat: false,
+            score: 0,
+            avatar: None,
+            color: None,
+            is_bot: false,
+            is_bot_controlled: false,
+            resume_token: String::new(),
         }
     }
 
@@ -42,22 +85,89 @@ impl Player {
 
         self.current_game = Some(game_id);
 
-        return Ok(());
+        Ok(())
+    }
+
+    pub fn spectate_game(&mut self, game_id: usize) -> Result<(), String> {
+        if self.current_game.is_some() {
+            return Err("Player is already in a game".to_string());
+        }
+
+        self.current_game = Some(game_id);
+        self.is_spectator = true;
+
+        Ok(())
     }
 
     // Removed lobby from the method signature
     pub fn leave_game(&mut self) -> Result<(), String> {
         self.current_game = None;
+        self.is_spectator = false;
+        self.is_waiting_for_seat = false;
         Ok(())
     }
 
     // ... (rest of your methods)
 
+    pub fn set_name(&mut self, name: &str) -> Result<(), String> {
+        self.name = validate_name(name)?;
+        Ok(())
+    }
+
+    pub fn set_profile(&mut self, avatar: Option<String>, color: Option<String>) -> Result<(), String> {
+        if let Some(avatar) = &avatar {
+            let valid_avatar =
+                !avatar.is_empty() &&
+                avatar.len() <= MAX_AVATAR_LENGTH &&
+                avatar.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_avatar {
+                return Err("Invalid avatar".to_string());
+            }
+        }
+        if let Some(color) = &color {
+            let valid_color =
+                color.len() == 7 &&
+                color.starts_with('#') &&
+                color[1..].chars().all(|c| c.is_ascii_hexdigit());
+            if !valid_color {
+                return Err("Color must look like #rrggbb".to_string());
+            }
+        }
+        self.avatar = avatar;
+        self.color = color;
+        Ok(())
+    }
+
     pub fn to_serializable(&self) -> SerializablePlayer {
         SerializablePlayer {
             id: self.id,
             name: self.name.clone(),
             card_count: self.hand.len(),
+            is_ready: self.is_ready,
+            score: self.score,
+            avatar: self.avatar.clone(),
+            color: self.color.clone(),
+            is_bot: self.is_bot,
+            is_bot_controlled: self.is_bot_controlled,
+        }
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        match (self.current_game, self.is_spectator) {
+            (None, _) => PlayerStatus::Idle,
+            (Some(_), true) => PlayerStatus::Spectating,
+            (Some(_), false) => PlayerStatus::InGame,
+        }
+    }
+
+    pub fn to_online_player(&self) -> OnlinePlayer {
+        OnlinePlayer {
+            id: self.id,
+            name: self.name.clone(),
+            avatar: self.avatar.clone(),
+            color: self.color.clone(),
+            status: self.status(),
+            game_id: self.current_game,
         }
     }
 
@@ -66,6 +176,18 @@ impl Player {
     }
 }
 
+//names are trimmed and may only contain letters, digits, spaces, '-' and '_'
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("Name must be between 1 and {} characters", MAX_NAME_LENGTH));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_') {
+        return Err("Name may only contain letters, numbers, spaces, '-' and '_'".to_string());
+    }
+    Ok(name.to_string())
+}
+
 //tests
 
 #[cfg(test)]
@@ -80,6 +202,7 @@ mod tests {
         assert!(player.hand.is_empty());
         assert!(player.current_game.is_none());
         assert!(!player.is_spectator);
+        assert!(!player.is_ready);
     }
 
     #[test]
@@ -89,6 +212,48 @@ mod tests {
         assert_eq!(player.current_game, Some(0));
     }
 
+    #[test]
+    fn test_player_spectate_game() {
+        let mut player = Player::new(0);
+        player.spectate_game(3).unwrap();
+        assert_eq!(player.current_game, Some(3));
+        assert!(player.is_spectator);
+        assert!(player.join_game(4).is_err());
+        player.leave_game().unwrap();
+        assert!(!player.is_spectator);
+    }
+
+    #[test]
+    fn test_player_status() {
+        let mut player = Player::new(0);
+        assert_eq!(player.status(), PlayerStatus::Idle);
+        player.join_game(1).unwrap();
+        assert_eq!(player.status(), PlayerStatus::InGame);
+        player.leave_game().unwrap();
+        player.spectate_game(1).unwrap();
+        assert_eq!(player.to_online_player().status, PlayerStatus::Spectating);
+    }
+
+    #[test]
+    fn test_player_set_name() {
+        let mut player = Player::new(0);
+        player.set_name("  Sofie_K ").unwrap();
+        assert_eq!(player.name, "Sofie_K");
+        assert!(player.set_name("").is_err());
+        assert!(player.set_name("<script>").is_err());
+        assert!(player.set_name(&"x".repeat(MAX_NAME_LENGTH + 1)).is_err());
+        assert_eq!(player.name, "Sofie_K");
+    }
+
+    #[test]
+    fn test_player_set_profile() {
+        let mut player = Player::new(0);
+        player.set_profile(Some("cat".to_string()), Some("#ff8800".to_string())).unwrap();
+        assert_eq!(player.color.as_deref(), Some("#ff8800"));
+        assert!(player.set_profile(None, Some("orange".to_string())).is_err());
+        assert!(player.set_profile(Some("../cat".to_string()), None).is_err());
+    }
+
     #[test]
     fn test_player_leave_game() {
         let mut player = Player::new(0);
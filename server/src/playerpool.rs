@@ -4,10 +4,16 @@ use crate::player::Player;
 
 #[derive(Debug)]
 pub struct PlayerConnection {
-    sender: Sender<String>,
+    sender: Option<Sender<String>>, // bots have no socket to send to
     pub player: Player,
 }
-#[derive(Debug)]
+impl PlayerConnection {
+    pub fn is_connected(&self) -> bool {
+        self.sender.is_some()
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct PlayerPool {
     pub connections: Vec<PlayerConnection>,
 }
@@ -39,7 +45,24 @@ impl PlayerPool {
     }
 
     pub fn register_connection(&mut self, sender: Sender<String>, player: Player) {
-        self.connections.push(PlayerConnection { sender, player });
+        self.connections.push(PlayerConnection { sender: Some(sender), player });
+    }
+
+    pub fn register_bot(&mut self, player: Player) {
+        self.connections.push(PlayerConnection { sender: None, player });
+    }
+
+    //keep the player in the pool but stop sending to them, e.g. when their socket closes mid-round
+    pub fn disconnect(&mut self, player_id: usize) {
+        if let Some(conn) = self.connections.iter_mut().find(|conn| conn.player.id == player_id) {
+            conn.sender = None;
+        }
+    }
+
+    pub fn reconnect(&mut self, player_id: usize, sender: Sender<String>) {
+        if let Some(conn) = self.connections.iter_mut().find(|conn| conn.player.id == player_id) {
+            conn.sender = Some(sender);
+        }
     }
 
     pub fn remove_connection(&mut self, player: Player) {
@@ -49,7 +72,10 @@ impl PlayerPool {
     pub async fn send_message(&self, player: &Player, message: String) {
         for conn in &self.connections {
             if conn.player.id == player.id {
-                if let Err(e) = conn.sender.send(message.clone()).await {
+                let Some(sender) = &conn.sender else {
+                    continue;
+                };
+                if let Err(e) = sender.send(message.clone()).await {
                     println!("Failed to send message to player {}: {}", player.id, e);
                 }
             }
@@ -59,7 +85,10 @@ impl PlayerPool {
     //broadcast message - sends to all players in the pool
     pub async fn broadcast_message(&self, message: String) {
         for conn in &self.connections {
-            if let Err(e) = conn.sender.send(message.clone()).await {
+            let Some(sender) = &conn.sender else {
+                continue;
+            };
+            if let Err(e) = sender.send(message.clone()).await {
                 println!("Failed to send message to player {}: {}", conn.player.id, e);
             }
         }
@@ -0,0 +1,243 @@
+use std::{ fmt::Debug, sync::{ Arc, Mutex }, time::Duration };
+
+use rand::{ rngs::StdRng, seq::SliceRandom, SeedableRng };
+use serde::{ Deserialize, Serialize };
+
+use crate::{ card::{ Card, Color, Value }, game_settings::{ GameSettings, Ruleset }, ismcts::IsmctsStrategy };
+
+// hard bots in seeded runs search a fixed number of iterations instead, so the run can be repeated
+pub const HARD_BOT_SEEDED_ITERATIONS: usize = 1000;
+
+// Everything a player at the table is allowed to know when it is their turn
+#[derive(Debug, Clone)]
+pub struct PlayerView {
+    pub player_id: usize,
+    pub hand: Vec<Card>,
+    pub top_card: Card,
+    pub active_color: Color,
+    pub discard_pile: Vec<Card>, // every card played since the deck was last reshuffled, top card last
+    pub opponents: Vec<OpponentView>, // in turn order, starting with the next player
+    pub direction: i8,
+    pub deck_size: usize,
+    pub rules: Ruleset,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpponentView {
+    pub player_id: usize,
+    pub card_count: usize,
+    pub misses: Vec<Miss>, // this round, oldest first
+}
+
+// A top card the opponent drew on instead of playing. Players hardly ever draw when they could
+// play, so they most likely held nothing playable on it - apart from the cards they drew since
+#[derive(Debug, Clone, PartialEq)]
+pub struct Miss {
+    pub top_card: Card,
+    pub drawn_since: usize,
+}
+
+impl PlayerView {
+    pub fn playable_cards(&self) -> Vec<&Card> {
+        self.hand
+            .iter()
+            .filter(|card| card.can_be_played_on(&self.top_card))
+            .collect()
+    }
+
+    //the color the player holds most of, which is the natural pick for a wild
+    pub fn best_color(&self) -> Color {
+        [Color::Red, Color::Yellow, Color::Green, Color::Blue]
+            .into_iter()
+            .max_by_key(|color| self.hand.iter().filter(|card| &card.color == color).count())
+            .unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotAction {
+    Play(Vec<Card>), // wild cards carry the chosen color
+    Draw,
+}
+
+pub trait Strategy: Send + Sync + Debug {
+    fn choose_action(&mut self, view: &PlayerView) -> BotAction;
+
+    //restart any randomness the strategy uses
+    fn reseed(&mut self, _seed: u64) {}
+}
+
+// a bot's strategy can be taken away from the table to think without holding on to the game
+pub type SharedStrategy = Arc<Mutex<Box<dyn Strategy>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotDifficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl BotDifficulty {
+    //the strategy for a live table. Hard bots search for as long as the table's settings allow, so
+    //their choices depend on the server's load as well as the seed
+    pub fn create_strategy(&self, seed: u64, settings: &GameSettings) -> SharedStrategy {
+        let strategy: Box<dyn Strategy> = match self {
+            BotDifficulty::Easy => Box::new(RandomStrategy::seeded(seed)),
+            BotDifficulty::Normal => Box::new(HeuristicStrategy),
+            BotDifficulty::Hard => Box::new(IsmctsStrategy::new(Duration::from_millis(settings.hard_bot_time_ms), seed)),
+        };
+        Arc::new(Mutex::new(strategy))
+    }
+
+    //a strategy that makes the same choices every time it is given the same seed
+    pub fn create_reproducible_strategy(&self, seed: u64) -> SharedStrategy {
+        let strategy: Box<dyn Strategy> = match self {
+            BotDifficulty::Easy => Box::new(RandomStrategy::seeded(seed)),
+            BotDifficulty::Normal => Box::new(HeuristicStrategy),
+            BotDifficulty::Hard => Box::new(IsmctsStrategy::with_iterations(HARD_BOT_SEEDED_ITERATIONS, seed)),
+        };
+        Arc::new(Mutex::new(strategy))
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BotDifficulty::Easy => "Easy",
+            BotDifficulty::Normal => "Normal",
+            BotDifficulty::Hard => "Hard",
+        }
+    }
+}
+
+fn with_chosen_color(card: &Card, color: Color) -> Card {
+    let mut card = card.clone();
+    if card.is_wild() {
+        card.color = color;
+    }
+    card
+}
+
+// Plays a random playable card, picking a random color for wilds
+#[derive(Debug)]
+pub struct RandomStrategy {
+    rng: StdRng,
+}
+
+impl RandomStrategy {
+    pub fn new() -> Self {
+        Self { rng: StdRng::from_entropy() }
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Self { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Default for RandomStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Strategy for RandomStrategy {
+    fn choose_action(&mut self, view: &PlayerView) -> BotAction {
+        match view.playable_cards().choose(&mut self.rng) {
+            Some(card) => {
+                let color = [Color::Red, Color::Yellow, Color::Green, Color::Blue]
+                    .choose(&mut self.rng)
+                    .unwrap()
+                    .clone();
+                BotAction::Play(vec![with_chosen_color(card, color)])
+            }
+            None => BotAction::Draw,
+        }
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+// Sheds high value cards first, saves wilds for when nothing else fits, and goes after the next
+// player with action cards once they are close to going out
+#[derive(Debug)]
+pub struct HeuristicStrategy;
+
+impl HeuristicStrategy {
+    fn score(card: &Card, view: &PlayerView) -> i32 {
+        let next_player_is_close = view.opponents
+            .first()
+            .is_some_and(|opponent| opponent.card_count <= 2);
+        match card.value {
+            Value::Wild => 0,
+            Value::WildDrawFour => if next_player_is_close { 100 } else { 1 },
+            Value::Skip | Value::DrawTwo | Value::Reverse if next_player_is_close => 90,
+            _ => 10 + card.value.to_points(),
+        }
+    }
+}
+
+impl Strategy for HeuristicStrategy {
+    fn choose_action(&mut self, view: &PlayerView) -> BotAction {
+        let Some(card) = view.playable_cards()
+            .into_iter()
+            .max_by_key(|card| HeuristicStrategy::score(card, view)) else {
+            return BotAction::Draw;
+        };
+
+        let card = with_chosen_color(card, view.best_color());
+        let mut cards = vec![card.clone()];
+        //get rid of duplicates of a number card in one go
+        if view.rules.multi_card_play && matches!(card.value, Value::Number(_)) {
+            cards.extend(
+                view.hand
+                    .iter()
+                    .filter(|other| other.value == card.value && other.id != card.id)
+                    .cloned()
+            );
+        }
+        BotAction::Play(cards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{ card, view };
+
+    #[test]
+    fn test_strategies_draw_without_playable_cards() {
+        let view = view(vec![card(1, Color::Blue, Value::Number(3))], card(2, Color::Red, Value::Number(5)), 5);
+        assert_eq!(RandomStrategy::new().choose_action(&view), BotAction::Draw);
+        assert_eq!(HeuristicStrategy.choose_action(&view), BotAction::Draw);
+    }
+
+    #[test]
+    fn test_heuristic_saves_wilds() {
+        let hand = vec![
+            card(1, Color::Wild, Value::Wild),
+            card(2, Color::Red, Value::Number(2)),
+            card(3, Color::Red, Value::Number(8)),
+        ];
+        let view = view(hand, card(4, Color::Red, Value::Number(5)), 5);
+        let BotAction::Play(cards) = HeuristicStrategy.choose_action(&view) else {
+            panic!("expected a play");
+        };
+        assert_eq!(cards[0].id, 3);
+    }
+
+    #[test]
+    fn test_heuristic_picks_color_for_wild() {
+        let hand = vec![
+            card(1, Color::Wild, Value::Wild),
+            card(2, Color::Green, Value::Number(2)),
+            card(3, Color::Green, Value::Number(4)),
+        ];
+        let view = view(hand, card(4, Color::Red, Value::Number(5)), 5);
+        let BotAction::Play(cards) = HeuristicStrategy.choose_action(&view) else {
+            panic!("expected a play");
+        };
+        assert_eq!(cards[0].id, 1);
+        assert_eq!(cards[0].color, Color::Green);
+    }
+}
@@ -0,0 +1,42 @@
+// Fixtures the unit tests of several modules share
+use tokio::sync::mpsc;
+
+use crate::{
+    card::{ Card, Color, Value },
+    game_settings::{ GameSettings, Ruleset },
+    game_state::GameState,
+    player::Player,
+    playerpool::PlayerPool,
+    strategy::{ OpponentView, PlayerView },
+};
+
+pub fn card(id: usize, color: Color, value: Value) -> Card {
+    Card { id, color, value }
+}
+
+// Player 1 to play on `top_card`, with player 2 - holding `next_player_cards` cards - up next
+pub fn view(hand: Vec<Card>, top_card: Card, next_player_cards: usize) -> PlayerView {
+    PlayerView {
+        player_id: 1,
+        hand,
+        active_color: top_card.color.clone(),
+        discard_pile: vec![top_card.clone()],
+        top_card,
+        opponents: vec![OpponentView { player_id: 2, card_count: next_player_cards, misses: Vec::new() }],
+        direction: 1,
+        deck_size: 50,
+        rules: Ruleset::default(),
+    }
+}
+
+// Builds a game with `count` seated players (ids 1..=count) hosted by player 1. The receiving ends
+// are dropped, so messages to players fail fast instead of filling up a channel nobody reads
+pub fn game_with_players(count: usize) -> GameState {
+    let mut game = GameState::new(1, PlayerPool::new(), 1, GameSettings::default());
+    for id in 1..=count {
+        let (tx, _) = mpsc::channel::<String>(1);
+        game.game_player_pool.register_connection(tx, Player::new(id));
+    }
+    game.player_to_play = 1;
+    game
+}
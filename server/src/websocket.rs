@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
 use crate::card::Card;
+use crate::chat::RateLimiter;
+use crate::game_settings::GameSettings;
 use crate::lobby::Lobby;
+use crate::matchmaking::MatchPreferences;
+use crate::strategy::BotDifficulty;
 use crate::player;
 use crate::playerpool::PlayerPool;
 use futures_util::SinkExt;
@@ -10,23 +14,55 @@ use serde_json::json;
 use warp::filters::ws::Message;
 use warp::ws::WebSocket;
 
-use rand::Rng;
+use rand::{ distributions::Alphanumeric, Rng };
 use serde::Deserialize;
 use tokio::sync::mpsc;
 
 use tokio::sync::Mutex;
 
+const RESUME_TOKEN_LENGTH: usize = 24;
+
 #[derive(Deserialize)]
 pub struct ClientMessage {
     pub action: String,
     pub game_id: Option<usize>,
     pub cards: Option<serde_json::Value>,
+    pub target_id: Option<usize>,
+    pub ready: Option<bool>,
+    pub settings: Option<GameSettings>,
+    pub invite_code: Option<String>,
+    pub password: Option<String>,
+    pub message: Option<String>,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub color: Option<String>,
+    pub invitation_id: Option<usize>,
+    pub preferences: Option<MatchPreferences>,
+    pub accept: Option<bool>,
+    pub difficulty: Option<BotDifficulty>,
+    pub resume_token: Option<String>,
+    pub entropy: Option<String>,
 }
 
-fn generate_player_id() -> usize {
-    // Generate a random number
-    let mut rng = rand::thread_rng();
-    rng.gen()
+fn generate_resume_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(RESUME_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn player_message(player: &player::Player) -> String {
+    let player_json =
+        json!({
+        "id": player.id,
+        "name": player.name,
+        "hand:": player.hand,
+        "current_game": player.current_game,
+        "is_spectator": player.is_spectator,
+        "resume_token": player.resume_token
+    }).to_string();
+    create_websocket_message("player", &player_json)
 }
 
 pub async fn handle_connection(
@@ -34,8 +70,9 @@ pub async fn handle_connection(
     lobby: Arc<Mutex<Lobby>>,
     player_pool: Arc<Mutex<PlayerPool>>
 ) {
-    let player_id = generate_player_id();
-    let player = player::Player::new(player_id);
+    let mut player_id = lobby.lock().await.generate_player_id().await;
+    let mut player = player::Player::new(player_id);
+    player.resume_token = generate_resume_token();
     let (tx, mut rx) = mpsc::channel::<String>(32);
     // Register player in PlayerPool
     {
@@ -43,19 +80,8 @@ pub async fn handle_connection(
         player_pool.register_connection(tx.clone(), player.clone());
     }
 
-    // Send the player ID to the client
-    //build the player as json and send it to the client
-    let player_json =
-        json!({
-        "id": player.id,
-        "name": player.name,
-        "hand:": player.hand,
-        "current_game": player.current_game,
-        "is_spectator": player.is_spectator
-    }).to_string();
-
-    //let player_json = serde_json::to_string(&player).unwrap();
-    let response = create_websocket_message("player", &player_json);
+    // Send the player ID (and the token to resume their seat with after a dropped connection) to the client
+    let response = player_message(&player);
     let _ = ws.send(Message::text(response)).await;
 
     // Send the list of games to the client
@@ -63,6 +89,14 @@ pub async fn handle_connection(
     let games_json = serde_json::to_string(&games).unwrap();
     let response = create_websocket_message("update_lobby_games_list", &games_json);
     let _ = ws.send(Message::text(response)).await;
+
+    // Catch the client up on the lobby chat
+    let response = lobby.lock().await.chat_history_message();
+    let _ = ws.send(Message::text(response)).await;
+
+    // Let everyone (including the new player) know who is online
+    lobby.lock().await.broadcast_presence().await;
+    let mut chat_limiter = RateLimiter::new();
     // Main event loop for this connection
     loop {
         tokio::select! {
@@ -75,22 +109,16 @@ pub async fn handle_connection(
                         // WebSocket connection was closed or an error occurred.
                         println!("WebSocket connection closed for player_id: {}", player_id);
 
-                        // Deregister the player from the PlayerPool
-                        let mut player_pool = player_pool.lock().await;
-                        player_pool.remove_connection(player);
-
-                            //get a list of games from the lobby and - if the player is found in any games player_pool. remove them from that pool
-                            let mut lobby = lobby.lock().await;
-                            let games = lobby.games.values_mut();
-                            for game in games {
-                                //if the game has the player in its player_pool, remove them from the pool
-                                if game.game_player_pool.connections.iter().any(|conn| conn.player.id == player_id) {
-                                    println!("Player {} found and removed from game {}", player_id, game.id);
-                                   let _ =  game.remove_player(player_id).await;
-                                }
-                            }
+                        // Deregister the player from the PlayerPool. The pool lock is released before the lobby is
+                        // locked, as lobby methods lock the pool themselves
+                        {
+                            let mut player_pool = player_pool.lock().await;
+                            player_pool.remove_connection(player);
+                        }
+
+                            //take the player out of the queue and their game, or have a bot keep their seat
+                            lobby.lock().await.disconnect_player(player_id).await;
                             println!("Player {} removed from player_pool", player_id);
-                            let _ = create_websocket_message("update_lobby_games_list", &games_json);   
                         break; // Exit the loop
                     },
                     _ => continue,
@@ -98,7 +126,7 @@ pub async fn handle_connection(
 
                     if msg.is_text() {
                     let text = msg.to_str().unwrap_or_default();
-                    let client_msg: Result<ClientMessage, _> = serde_json::from_str(&text);
+                    let client_msg: Result<ClientMessage, _> = serde_json::from_str(text);
                     if let Err(e) = client_msg {
                         println!("Failed to parse client message: {}", e);
                         continue;
@@ -108,6 +136,20 @@ pub async fn handle_connection(
 
 
                         match client_msg.action.as_str() {
+                            "fetch_finished_matches" => {
+                                let matches = lobby.lock().await.list_archived_matches();
+                                let matches_json = serde_json::to_string(&matches).unwrap();
+                                let response = create_websocket_message("finished_matches_list", &matches_json);
+                                let _ = ws.send(Message::text(response)).await;
+                            }
+
+                            "fetch_online_players" => {
+                                let online_players = lobby.lock().await.list_online_players().await;
+                                let online_players_json = serde_json::to_string(&online_players).unwrap();
+                                let response = create_websocket_message("update_online_players", &online_players_json);
+                                let _ = ws.send(Message::text(response)).await;
+                            }
+
                             "fetch_games" => {
                               
                                     let lobby = lobby.lock().await;
@@ -117,53 +159,51 @@ pub async fn handle_connection(
                             "create_game" => {
                                 let mut lobby = lobby.lock().await;
                                 println!("Creating game");
-                                let _ = {
-
-                                    lobby.create_game().await
-                                };
-
-
-                            }
-
-                            "join_game" => {
-
-                                let game_id = client_msg.game_id.unwrap();
-                                let result;
-                                {
-                                let mut player_pool = player_pool.lock().await;
-                                if let Some(mut player) = player_pool.get_player_by_id(player_id) {
-                                    result = player.join_game(game_id);
-                                    if result.is_ok() {
-                                        player.current_game = Some(game_id);
-                                        // Update the player in the pool
-                                        player_pool.update_player(player);
+                                let settings = client_msg.settings.unwrap_or_default();
+                                match lobby.create_game(player_id, tx.clone(), settings, client_msg.password).await {
+                                    Ok(game_id) => {
+                                        //the host needs the invite code to share a private game
+                                        let game_created_json = json!({
+                                            "id": game_id,
+                                            "invite_code": lobby.games[&game_id].invite_code,
+                                        }).to_string();
+                                        let message = create_websocket_message("game_created", &game_created_json);
+                                        let _ = ws.send(Message::text(message)).await;
+                                        let message = create_websocket_message("you_joined_game", &game_id.to_string());
+                                        let _ = ws.send(Message::text(message)).await;
+                                    }
+                                    Err(err) => {
+                                        let message = create_websocket_message("error", &err);
+                                        let _ = ws.send(Message::text(message)).await;
                                     }
-                                } else {
-                                    // Handle player not found
-                                    continue; 
                                 }
                             }
 
-                                // Use match to handle the Result
-                                match result {
+                            "join_game" | "spectate_game" => {
+                                let as_spectator = client_msg.action == "spectate_game";
+                                let mut lobby = lobby.lock().await;
+                                let invite_code = client_msg.invite_code.as_deref();
+                                let game_id = match (client_msg.game_id, invite_code) {
+                                    (Some(game_id), _) => Some(game_id),
+                                    (None, Some(code)) => lobby.find_game_by_invite_code(code),
+                                    (None, None) => None,
+                                };
+                                let Some(game_id) = game_id else {
+                                    let response = create_websocket_message("error", "Invalid invite code");
+                                    let _ = ws.send(Message::text(response)).await;
+                                    continue;
+                                };
+                                if let Err(err) = lobby.authorize_join(game_id, invite_code, client_msg.password.as_deref()) {
+                                    let response = create_websocket_message("error", err);
+                                    let _ = ws.send(Message::text(response)).await;
+                                    continue;
+                                }
+                                match lobby.join_game(game_id, player_id, tx.clone(), as_spectator).await {
                                     Ok(_) => {
-
-                                        {
-                                            let player_pool = player_pool.lock().await;
-                                            let mut player = player_pool.get_player_by_id(player_id).unwrap();
-                                            player.current_game = Some(game_id);
-                                            let response = create_websocket_message("you_joined_game", &game_id.to_string());
-                                            let _ = player_pool.send_message(&player, response).await;
-                                        }
-
-                                        // Add the player to the game   
-                                        let mut lobby = lobby.lock().await;
-                                        let game = lobby.games.get_mut(&game_id).unwrap();
-                                        let _ = game.add_player(tx.clone(), player.clone()).await;
-                                        let _ = lobby.broadcast_lobby_gamelist().await;
+                                        let response = create_websocket_message("you_joined_game", &game_id.to_string());
+                                        let _ = ws.send(Message::text(response)).await;
                                     },
                                     Err(err_msg) => {
-
                                         // If Err, send a message to the client and continue to the next iteration
                                         let response = create_websocket_message("error", &err_msg);
                                         let _ = ws.send(Message::text(response)).await;
@@ -181,11 +221,16 @@ pub async fn handle_connection(
                                     
                                     
                                                             //get the game_id from the client message
-                                                            let game_id = client_msg.game_id.unwrap();
+                                                            let Some(game_id) = client_msg.game_id else {
+                                                                let message = create_websocket_message("error", "No game provided");
+                                                                let _ = ws.send(Message::text(message)).await;
+                                                                continue;
+                                                            };
                                                             let mut lobby = lobby.lock().await;
                                                             if let Some(game) = lobby.games.get_mut(&game_id) {
                                                                 match game.play_cards(player_id, cards).await {
                                                                     Ok(_) => {
+                                                                        game.missed_turns.remove(&player_id);
                                                                         // Notify the player that the card was successfully played
                                                                         let message = create_websocket_message("card_played", "ok");
                                                                         let _ = ws.send(Message::text(message)).await;
@@ -209,13 +254,329 @@ pub async fn handle_connection(
                             }
 
                     }
+                    "chat" => {
+                        let text = client_msg.message.unwrap_or_default();
+                        let mut lobby = lobby.lock().await;
+                        //without a game id the message goes to the whole lobby
+                        let result = match chat_limiter.check() {
+                            Err(err) => Err(err),
+                            Ok(_) => match client_msg.game_id {
+                                Some(game_id) => match lobby.games.get_mut(&game_id) {
+                                    Some(game) => game.send_chat(player_id, &text).await,
+                                    None => Err("Game not found"),
+                                },
+                                None => lobby.send_chat(player_id, &text).await,
+                            },
+                        };
+                        if let Err(err) = result {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "set_name" => {
+                        let mut lobby = lobby.lock().await;
+                        let result = lobby.set_profile(
+                            player_id,
+                            client_msg.name.as_deref(),
+                            client_msg.avatar,
+                            client_msg.color
+                        ).await;
+                        if let Err(err) = result {
+                            let message = create_websocket_message("error", &err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "invite_player" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let Some(target_id) = client_msg.target_id else {
+                            let message = create_websocket_message("error", "No target player provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        if let Err(err) = lobby.invite_player(player_id, game_id, target_id).await {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "accept_invitation" | "decline_invitation" => {
+                        let invitation_id = client_msg.invitation_id.unwrap_or_default();
+                        let mut lobby = lobby.lock().await;
+                        if client_msg.action == "accept_invitation" {
+                            match lobby.accept_invitation(player_id, invitation_id, tx.clone()).await {
+                                Ok(game_id) => {
+                                    let response = create_websocket_message("you_joined_game", &game_id.to_string());
+                                    let _ = ws.send(Message::text(response)).await;
+                                }
+                                Err(err) => {
+                                    let message = create_websocket_message("error", &err);
+                                    let _ = ws.send(Message::text(message)).await;
+                                }
+                            }
+                        } else if let Err(err) = lobby.decline_invitation(player_id, invitation_id).await {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "join_queue" => {
+                        let preferences = client_msg.preferences.unwrap_or_default();
+                        let mut lobby = lobby.lock().await;
+                        if let Err(err) = lobby.join_queue(player_id, tx.clone(), preferences).await {
+                            let message = create_websocket_message("error", &err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "leave_queue" => {
+                        let mut lobby = lobby.lock().await;
+                        match lobby.leave_queue(player_id).await {
+                            Ok(_) => {
+                                let status_json = json!({ "status": "cancelled" }).to_string();
+                                let message = create_websocket_message("matchmaking_status", &status_json);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                            Err(err) => {
+                                let message = create_websocket_message("error", err);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                        }
+                    }
+                    "rematch_vote" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let accept = client_msg.accept.unwrap_or(false);
+                        let mut lobby = lobby.lock().await;
+                        if let Err(err) = lobby.vote_rematch(game_id, player_id, accept).await {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "pause_game" | "resume_game" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let paused = client_msg.action == "pause_game";
+                        let mut lobby = lobby.lock().await;
+                        let result = match lobby.games.get_mut(&game_id) {
+                            Some(game) => game.request_pause(player_id, paused).await,
+                            None => Err("Game not found"),
+                        };
+                        if let Err(err) = result {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "add_bot" | "remove_bot" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        let result = match lobby.games.get_mut(&game_id) {
+                            Some(game) if client_msg.action == "add_bot" => {
+                                let difficulty = client_msg.difficulty.unwrap_or(BotDifficulty::Normal);
+                                game.add_bot(player_id, difficulty).await.map(|_| ())
+                            }
+                            Some(game) => match client_msg.target_id {
+                                Some(bot_id) => game.remove_bot(player_id, bot_id).await,
+                                None => Err("No target player provided"),
+                            },
+                            None => Err("Game not found"),
+                        };
+                        match result {
+                            Ok(_) => {
+                                let _ = lobby.broadcast_lobby_gamelist().await;
+                            }
+                            Err(err) => {
+                                let message = create_websocket_message("error", err);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                        }
+                    }
+                    "start_game" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        let result = match lobby.games.get_mut(&game_id) {
+                            Some(game) => game.start_game(player_id).await,
+                            None => Err("Game not found"),
+                        };
+                        match result {
+                            Ok(_) => {
+                                let _ = lobby.broadcast_lobby_gamelist().await;
+                            }
+                            Err(err) => {
+                                let message = create_websocket_message("error", err);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                        }
+                    }
+                    "set_ready" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let ready = client_msg.ready.unwrap_or(true);
+                        let mut lobby = lobby.lock().await;
+                        let result = match lobby.games.get_mut(&game_id) {
+                            Some(game) => game.set_ready(player_id, ready).await,
+                            None => Err("Game not found"),
+                        };
+                        match result {
+                            Ok(_) => {
+                                let _ = lobby.broadcast_lobby_gamelist().await;
+                            }
+                            Err(err) => {
+                                let message = create_websocket_message("error", err);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                        }
+                    }
+                    "contribute_entropy" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let entropy = client_msg.entropy.unwrap_or_default();
+                        let mut lobby = lobby.lock().await;
+                        let result = match lobby.games.get_mut(&game_id) {
+                            Some(game) => game.contribute_entropy(player_id, &entropy).await,
+                            None => Err("Game not found".to_string()),
+                        };
+                        if let Err(err) = result {
+                            let message = create_websocket_message("error", &err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "resume_seat" => {
+                        //a reconnected client takes over the identity of the player whose seat it resumes
+                        let resume_token = client_msg.resume_token.unwrap_or_default();
+                        let mut lobby = lobby.lock().await;
+                        let in_game = player_pool.lock().await
+                            .get_player_by_id(player_id)
+                            .is_some_and(|player| player.current_game.is_some());
+                        let result = if in_game {
+                            Err("Leave your current game first")
+                        } else {
+                            lobby.resume_seat(&resume_token, tx.clone()).await
+                        };
+                        match result {
+                            Ok(resumed) => {
+                                let _ = lobby.leave_queue(player_id).await;
+                                {
+                                    let mut player_pool = player_pool.lock().await;
+                                    player_pool.remove_connection(player.clone());
+                                    player_pool.register_connection(tx.clone(), resumed.clone());
+                                }
+                                player_id = resumed.id;
+                                player = resumed;
+                                let _ = ws.send(Message::text(player_message(&player))).await;
+                                let _ = lobby.broadcast_lobby_gamelist().await;
+                                lobby.broadcast_presence().await;
+                            }
+                            Err(err) => {
+                                let message = create_websocket_message("error", err);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                        }
+                    }
+                    "resume_control" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        let result = match lobby.games.get_mut(&game_id) {
+                            Some(game) => game.resume_control(player_id, None).await,
+                            None => Err("Game not found"),
+                        };
+                        if let Err(err) = result {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "leave_game" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        match lobby.leave_game(game_id, player_id).await {
+                            Ok(_) => {
+                                let message = create_websocket_message("you_left_game", &game_id.to_string());
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                            Err(err) => {
+                                let message = create_websocket_message("error", err);
+                                let _ = ws.send(Message::text(message)).await;
+                            }
+                        }
+                    }
+                    "kick_player" | "transfer_host" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let Some(target_id) = client_msg.target_id else {
+                            let message = create_websocket_message("error", "No target player provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        let result = if client_msg.action == "kick_player" {
+                            lobby.kick_player(game_id, player_id, target_id).await
+                        } else {
+                            match lobby.games.get_mut(&game_id) {
+                                Some(game) => game.transfer_host(player_id, target_id).await,
+                                None => Err("Game not found"),
+                            }
+                        };
+                        if let Err(err) = result {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
+                    "close_game" => {
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
+                        let mut lobby = lobby.lock().await;
+                        if let Err(err) = lobby.close_game(game_id, player_id).await {
+                            let message = create_websocket_message("error", err);
+                            let _ = ws.send(Message::text(message)).await;
+                        }
+                    }
                     "draw_card" => {
                         //get the game_id from the client message
-                        let game_id = client_msg.game_id.unwrap();
+                        let Some(game_id) = client_msg.game_id else {
+                            let message = create_websocket_message("error", "No game provided");
+                            let _ = ws.send(Message::text(message)).await;
+                            continue;
+                        };
                         let mut lobby = lobby.lock().await;
                         if let Some(game) = lobby.games.get_mut(&game_id) {
-                            match game.draw_cards(player_id, 1, true).await {
+                            match game.draw_for_turn(player_id).await {
                                 Ok(_) => {
+                                    game.missed_turns.remove(&player_id);
                                     // Notify the player that the card was successfully played
                                     let message = create_websocket_message("card_drawn", "ok");
                                     let _ = ws.send(Message::text(message)).await;